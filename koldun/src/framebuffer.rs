@@ -0,0 +1,281 @@
+use crate::ili9486::gram::Gram;
+use crate::ili9486::{
+    expand_bits, Command, Display, DrawTargetText, GameDisplay, Order, PixelFormat, STREAM_ROWS,
+};
+use alloc::boxed::Box;
+use alloc::vec::Vec as AllocVec;
+use async_trait::async_trait;
+use core::convert::Infallible;
+use embedded_graphics::draw_target::DrawTarget;
+use embedded_graphics::mono_font::iso_8859_5::FONT_9X15_BOLD;
+use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::{BinaryColor, Rgb565};
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use embedded_graphics::text::Text;
+use embedded_graphics::Pixel;
+use heapless::Vec;
+use tinytga::Tga;
+extern crate alloc;
+
+pub use crate::ili9486::gram::{HEIGHT, WIDTH};
+
+/// In-memory stand-in for the ILI9486, draws straight into a `Gram` so the
+/// frame ends up the same as on the real panel.
+pub struct FramebufferDisplay {
+    gram: Gram,
+}
+
+impl FramebufferDisplay {
+    pub fn new() -> Self {
+        FramebufferDisplay { gram: Gram::new() }
+    }
+
+    pub fn gram(&self) -> &Gram {
+        &self.gram
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb565 {
+        self.gram.pixel(x, y)
+    }
+
+    /// Raw RGB565 values, row by row
+    pub fn raw(&self) -> &[u16] {
+        self.gram.raw()
+    }
+
+    /// Value last sent with `MemoryAccessControl`
+    pub fn madctl(&self) -> u8 {
+        self.gram.madctl()
+    }
+
+    pub fn pixel_format(&self) -> u8 {
+        self.gram.pixel_format()
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.gram.is_on()
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.gram.brightness()
+    }
+
+    /// Frame as 8 bit RGB triplets, row by row
+    pub fn to_rgb888(&self) -> AllocVec<u8> {
+        self.gram.to_rgb888()
+    }
+
+    #[cfg(feature = "host")]
+    pub fn write_png<W: std::io::Write>(&self, writer: W) -> Result<(), png::EncodingError> {
+        let mut encoder = png::Encoder::new(writer, WIDTH as u32, HEIGHT as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.to_rgb888())
+    }
+
+    #[cfg(feature = "host")]
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> Result<(), png::EncodingError> {
+        let file = std::fs::File::create(path)?;
+        self.write_png(std::io::BufWriter::new(file))
+    }
+
+    fn color_to_data(color: Rgb565) -> [u8; 2] {
+        let b = color.to_ne_bytes();
+        [b[1], b[0]]
+    }
+
+    fn binary_to_data(color: BinaryColor, fg: Rgb565, bg: Rgb565) -> [u8; 2] {
+        match color.is_on() {
+            true => Self::color_to_data(fg),
+            false => Self::color_to_data(bg),
+        }
+    }
+}
+
+impl Default for FramebufferDisplay {
+    fn default() -> Self {
+        FramebufferDisplay::new()
+    }
+}
+
+impl Dimensions for FramebufferDisplay {
+    fn bounding_box(&self) -> Rectangle {
+        Rectangle::new(Point::new(0, 0), Size::new(WIDTH as u32, HEIGHT as u32))
+    }
+}
+
+impl DrawTarget for FramebufferDisplay {
+    type Color = Rgb565;
+    type Error = Infallible;
+
+    fn draw_iter<I>(&mut self, pixels: I) -> Result<(), Self::Error>
+    where
+        I: IntoIterator<Item = Pixel<Self::Color>>,
+    {
+        for Pixel(coord, color) in pixels.into_iter() {
+            if let Ok((x, y)) = TryInto::<(u32, u32)>::try_into(coord) {
+                self.gram.set_pixel(x as usize, y as usize, color);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl DrawTargetText for FramebufferDisplay {
+    fn draw_text(
+        &mut self,
+        text: &str,
+        position: Point,
+        color: Self::Color,
+        bg: Option<Self::Color>,
+    ) {
+        let mut style = MonoTextStyle::new(&FONT_9X15_BOLD, color);
+        style.background_color = bg;
+        Text::new(text, position, style).draw(self).unwrap();
+    }
+}
+
+#[async_trait]
+impl Display<u8> for FramebufferDisplay {
+    type Color = Rgb565;
+
+    async fn set_active_area(&mut self, area: Rectangle) {
+        let start = area.top_left;
+        if let Some(end) = area.bottom_right() {
+            self.column_address_set(start.x as u16, end.x as u16).await;
+            self.page_address_set(start.y as u16, end.y as u16).await;
+        }
+    }
+
+    async fn set_pixel_format(&mut self, pixel: PixelFormat) {
+        self.gram.set_pixel_format(pixel as u8);
+    }
+
+    async fn sleep_out(&mut self) {}
+
+    async fn inversion_off(&mut self) {}
+
+    async fn memory_access_control(
+        &mut self,
+        row_order: Order,
+        column_order: Order,
+        rc_exchange: Order,
+        vert_refresh: Order,
+        hor_refresh: Order,
+        color: Order,
+    ) {
+        let bit = |order: Order, shift: u8| match order {
+            Order::Forward => 0,
+            Order::Reverse => 1 << shift,
+        };
+
+        self.gram.set_madctl(
+            bit(hor_refresh, 2)
+                | bit(color, 3)
+                | bit(vert_refresh, 4)
+                | bit(rc_exchange, 5)
+                | bit(column_order, 6)
+                | bit(row_order, 7),
+        );
+    }
+
+    async fn norma_display_mode(&mut self) {}
+
+    async fn display_on(&mut self) {
+        self.gram.set_display_on(true);
+    }
+
+    async fn idle_mode_off(&mut self) {}
+
+    async fn set_brightness(&mut self, value: u8) {
+        self.gram.set_brightness(value);
+    }
+
+    async fn draw_data(&mut self, area: Rectangle, data: &[u8]) {
+        self.set_active_area(area).await;
+        self.gram.memory_write(data);
+    }
+
+    async fn draw_solid(&mut self, origin: Point, color: Self::Color) {
+        let color = Self::color_to_data(color);
+        let data = &[color; 32 * 32];
+        let mut v: Vec<[u8; 2], { 32 * 32 }> = Vec::new();
+        v.extend_from_slice(data).unwrap();
+
+        self.draw_tile(origin, v.flatten()).await;
+    }
+
+    async fn draw_solid_area(&mut self, area: Rectangle, color: Self::Color) {
+        let area = self.bounding_box().intersection(&area);
+        if let Some(bottom_right) = area.bottom_right() {
+            for x in (area.top_left.x..bottom_right.x).step_by(32) {
+                for y in (area.top_left.y..bottom_right.y).step_by(32) {
+                    self.draw_solid(Point::new(x, y), color).await;
+                }
+            }
+        }
+    }
+
+    async fn draw_tile(&mut self, origin: Point, data: &[u8]) {
+        let area = Rectangle::new(origin, Size::new(32, 32));
+        self.set_active_area(area).await;
+        self.gram.memory_write(data);
+    }
+
+    async fn draw_tile_bits(
+        &mut self,
+        origin: Point,
+        bits: &[u8],
+        depth: usize,
+        colors: &[Self::Color],
+    ) {
+        let area = Rectangle::new(origin, Size::new(32, 32));
+        self.set_active_area(area).await;
+
+        let mut buffer = [0u8; STREAM_ROWS * 32 * 2];
+        let mut command = Command::MemoryWrite;
+        for chunk in bits.chunks(STREAM_ROWS * 32 * depth / 8) {
+            let data = &mut buffer[..chunk.len() * 16 / depth];
+            expand_bits(chunk, depth, colors, data);
+            self.gram.apply(command, data);
+            command = Command::MemoryWriteContinue;
+        }
+    }
+
+    async fn tearing_effect_line_on(&mut self) {}
+
+    async fn column_address_set(&mut self, start: u16, end: u16) {
+        self.gram.column_address_set(start, end);
+    }
+
+    async fn page_address_set(&mut self, start: u16, end: u16) {
+        self.gram.page_address_set(start, end);
+    }
+
+    fn tga_to_data(data: &[u8]) -> Vec<u8, { 32 * 32 * 2 }> {
+        let tga: Tga<Self::Color> = Tga::from_slice(data).unwrap();
+        let pixels: Vec<_, { 32 * 32 * 2 }> = tga
+            .pixels()
+            .map(|p| Self::color_to_data(p.1))
+            .flatten()
+            .collect();
+
+        pixels
+    }
+
+    fn render_bin_tga(data: &[u8], fg: Self::Color, bg: Self::Color) -> Vec<u8, { 32 * 32 * 2 }> {
+        let tga: Tga<BinaryColor> = Tga::from_slice(data).unwrap();
+        let pixels: Vec<_, { 32 * 32 * 2 }> = tga
+            .pixels()
+            .map(|p| Self::binary_to_data(p.1, fg, bg))
+            .flatten()
+            .collect();
+
+        pixels
+    }
+}
+
+#[async_trait]
+impl GameDisplay for FramebufferDisplay {}
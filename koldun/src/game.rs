@@ -3,8 +3,13 @@ extern crate alloc;
 pub mod colors;
 pub mod events;
 pub mod flash;
+pub mod replay;
+pub mod save;
+pub mod settings;
 pub mod state_mashine;
+pub mod text;
+pub mod tile_cache;
 pub mod tiles;
 
-pub const MAX_X: usize = 15;
-pub const MAX_Y: usize = 10;
+pub const MAX_X: usize = koldun_level_format::WIDTH;
+pub const MAX_Y: usize = koldun_level_format::HEIGHT;
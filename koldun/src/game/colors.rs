@@ -4,7 +4,119 @@ pub const START_MENU_BG: Rgb565 = Rgb565::new(4, 1, 10);
 pub const START_MENU_TILE: Rgb565 = Rgb565::new(150, 0, 0);
 pub const START_MENU_TEXT: Rgb565 = Rgb565::new(100, 0, 0);
 pub const START_MENU_TEXT_BG: Rgb565 = Rgb565::new(11, 12, 43);
+pub const START_MENU_TEXT_DISABLED: Rgb565 = Rgb565::new(8, 16, 8);
 pub const DARK_RED: Rgb565 = Rgb565::new(10, 0, 0);
 pub const WALL_FG: Rgb565 = Rgb565::new(60, 50, 3);
 pub const WIZARD_FG: Rgb565 = Rgb565::new(100, 100, 100);
 pub const WALL_BG: Rgb565 = Rgb565::new(0, 0, 1);
+
+/// Palette colour a tile is drawn with, declared per tile in `tiles.rs`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum Ink {
+    WallFg,
+    WallBg,
+    WizardFg,
+}
+
+/// Colours an indexed tile can use, 16 for a 4bpp sheet
+pub const TILE_COLORS: usize = 16;
+
+/// Colours of an indexed tile, a pixel of value `n` is drawn in the `n`th one.
+/// Two-colour tiles use the first two, background then foreground
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TilePalette(pub [Rgb565; TILE_COLORS]);
+
+impl TilePalette {
+    /// Palette of a two-colour tile
+    pub const fn pair(bg: Rgb565, fg: Rgb565) -> Self {
+        let mut colors = [bg; TILE_COLORS];
+        colors[1] = fg;
+        TilePalette(colors)
+    }
+
+    /// `TILE_COLORS` shades from `dark` to `light`, given as red, green and blue
+    pub const fn ramp(dark: (u8, u8, u8), light: (u8, u8, u8)) -> Self {
+        let mut colors = [Rgb565::new(dark.0, dark.1, dark.2); TILE_COLORS];
+        let mut step = 1;
+        while step < TILE_COLORS {
+            colors[step] = Rgb565::new(
+                mix(dark.0, light.0, step),
+                mix(dark.1, light.1, step),
+                mix(dark.2, light.2, step),
+            );
+            step += 1;
+        }
+        TilePalette(colors)
+    }
+
+    /// The first `count` colours, as many as a tile of that depth indexes
+    pub fn colors(&self, count: usize) -> &[Rgb565] {
+        &self.0[..count.min(TILE_COLORS)]
+    }
+}
+
+const fn mix(from: u8, to: u8, step: usize) -> u8 {
+    let last = TILE_COLORS - 1;
+    ((from as usize * (last - step) + to as usize * step) / last) as u8
+}
+
+/// Colours of a `Theme`
+pub struct Palette {
+    pub wall_fg: Rgb565,
+    pub wall_bg: Rgb565,
+    pub wizard_fg: Rgb565,
+    pub menu_bg: Rgb565,
+    pub menu_title: Rgb565,
+    pub menu_text: Rgb565,
+    pub menu_text_bg: Rgb565,
+    pub menu_text_disabled: Rgb565,
+    /// Colours of the tiles from indexed sheets
+    pub tiles: TilePalette,
+}
+
+impl Palette {
+    pub fn ink(&self, ink: Ink) -> Rgb565 {
+        match ink {
+            Ink::WallFg => self.wall_fg,
+            Ink::WallBg => self.wall_bg,
+            Ink::WizardFg => self.wizard_fg,
+        }
+    }
+}
+
+pub const DUNGEON: Palette = Palette {
+    wall_fg: WALL_FG,
+    wall_bg: WALL_BG,
+    wizard_fg: WIZARD_FG,
+    menu_bg: START_MENU_BG,
+    menu_title: START_MENU_TILE,
+    menu_text: START_MENU_TEXT,
+    menu_text_bg: START_MENU_TEXT_BG,
+    menu_text_disabled: START_MENU_TEXT_DISABLED,
+    tiles: TilePalette::ramp((0, 0, 1), (28, 50, 3)),
+};
+
+pub const FOREST: Palette = Palette {
+    wall_fg: Rgb565::new(6, 36, 4),
+    wall_bg: Rgb565::new(1, 4, 1),
+    wizard_fg: Rgb565::new(28, 56, 20),
+    menu_bg: Rgb565::new(2, 8, 2),
+    menu_title: Rgb565::new(26, 50, 6),
+    menu_text: Rgb565::new(20, 44, 12),
+    menu_text_bg: Rgb565::new(4, 20, 6),
+    menu_text_disabled: Rgb565::new(8, 18, 8),
+    tiles: TilePalette::ramp((1, 4, 1), (28, 56, 20)),
+};
+
+pub const ICE: Palette = Palette {
+    wall_fg: Rgb565::new(18, 48, 31),
+    wall_bg: Rgb565::new(0, 4, 10),
+    wizard_fg: Rgb565::new(31, 63, 31),
+    menu_bg: Rgb565::new(2, 8, 14),
+    menu_title: Rgb565::new(24, 60, 31),
+    menu_text: Rgb565::new(20, 50, 31),
+    menu_text_bg: Rgb565::new(6, 20, 24),
+    menu_text_disabled: Rgb565::new(10, 22, 16),
+    tiles: TilePalette::ramp((0, 4, 10), (31, 63, 31)),
+};
@@ -1,12 +1,10 @@
-use defmt::Format;
-
-#[derive(Format)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum Event {
     Button(Buttons),
     Tick(u128),
 }
 
-#[derive(Format)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum Buttons {
     Up(States),
     Down(States),
@@ -15,7 +13,7 @@ pub enum Buttons {
     Reset(States),
 }
 
-#[derive(Format)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum States {
     Pressed,
     Released,
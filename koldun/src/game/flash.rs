@@ -1,28 +1,106 @@
 use alloc::{boxed::Box, vec::Vec};
 use async_trait::async_trait;
+use core::fmt;
+#[cfg(feature = "rp2040")]
 use core::mem::transmute;
-use embassy_rp::flash::{Async, Flash as RPFlash, Instance};
+#[cfg(feature = "rp2040")]
+use embassy_rp::flash::{Async, Flash as RPFlash, Instance, ERASE_SIZE};
 extern crate alloc;
 
-const ADDR_OFFSET: usize = 0x100000; // 1Mb offset
-const FLASH_SIZE: usize = 2 * 1024 * 1024;
+#[cfg(feature = "host")]
+mod host;
+#[cfg(feature = "host")]
+pub use host::{FileFlash, RamFlash, ERASED};
+
+pub const ADDR_OFFSET: usize = 0x100000; // 1Mb offset
+pub const FLASH_SIZE: usize = 2 * 1024 * 1024;
+
+pub const LEVELS_OFFSET: usize = 0x0; // Relative to ADDR_OFFSET
+pub const REPLAY_OFFSET: usize = 0x10000; // Relative to ADDR_OFFSET
+
+/// Levels, recordings and other assets flashed with `probe-rs`, never written by the game
+pub const ASSETS_SIZE: usize = 0x80000;
+/// Region the game may erase and write, right above the assets. Relative to ADDR_OFFSET
+pub const STORAGE_OFFSET: usize = ASSETS_SIZE;
+pub const STORAGE_SIZE: usize = FLASH_SIZE - ADDR_OFFSET - STORAGE_OFFSET;
+/// Smallest erasable unit
+pub const SECTOR_SIZE: usize = 4096;
+
+#[cfg(feature = "rp2040")]
+const _: () = assert!(SECTOR_SIZE == ERASE_SIZE);
 
 #[async_trait]
 pub trait Flash {
     async fn load(&mut self, offset: usize, buf: &mut [u32]);
     async fn load_tga<const SIZE: usize, const SIZE2: usize>(&mut self, offset: usize) -> Vec<u8>;
+    /// Erases `len` bytes at `offset`, both must be multiples of `SECTOR_SIZE`
+    async fn erase(&mut self, offset: usize, len: usize) -> Result<(), FlashError>;
+    /// Programs `data` at `offset`. Programming only clears bits, so the range
+    /// has to be erased first
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum FlashError {
+    /// Range starts below `STORAGE_OFFSET`, in the assets or the firmware
+    Protected(usize),
+    /// Range ends past the storage region
+    OutOfBounds(usize),
+    /// Erase not aligned to `SECTOR_SIZE`
+    Unaligned(usize),
+    /// The flash itself failed
+    Device,
+}
+
+impl fmt::Display for FlashError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FlashError::Protected(offset) => write!(f, "Offset {:#x} is not in storage", offset),
+            FlashError::OutOfBounds(offset) => {
+                write!(f, "Range at {:#x} runs past the storage", offset)
+            }
+            FlashError::Unaligned(offset) => {
+                write!(f, "Erase at {:#x} is not sector aligned", offset)
+            }
+            FlashError::Device => write!(f, "Flash failed"),
+        }
+    }
+}
+
+/// Checks that `len` bytes at `offset` lie inside the storage region
+pub fn check_storage(offset: usize, len: usize) -> Result<(), FlashError> {
+    if offset < STORAGE_OFFSET {
+        return Err(FlashError::Protected(offset));
+    }
+    if len > STORAGE_SIZE || offset - STORAGE_OFFSET > STORAGE_SIZE - len {
+        return Err(FlashError::OutOfBounds(offset));
+    }
+    Ok(())
+}
+
+/// `check_storage` plus the sector alignment an erase needs
+pub fn check_erase(offset: usize, len: usize) -> Result<(), FlashError> {
+    check_storage(offset, len)?;
+    if offset % SECTOR_SIZE != 0 || len % SECTOR_SIZE != 0 {
+        return Err(FlashError::Unaligned(offset));
+    }
+    Ok(())
 }
 
+#[cfg(feature = "rp2040")]
 pub struct FlashAccess<'a, T: Instance> {
     flash: RPFlash<'a, T, Async, FLASH_SIZE>,
 }
 
+#[cfg(feature = "rp2040")]
 impl<'a, T: Instance> FlashAccess<'a, T> {
     pub fn new(flash: RPFlash<'a, T, Async, FLASH_SIZE>) -> Self {
         FlashAccess { flash }
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[async_trait]
 impl<'a, T: Instance + Send> Flash for FlashAccess<'a, T> {
     async fn load(&mut self, offset: usize, buf: &mut [u32]) {
@@ -44,4 +122,19 @@ impl<'a, T: Instance + Send> Flash for FlashAccess<'a, T> {
         vec_u8.extend_from_slice(data);
         vec_u8
     }
+
+    async fn erase(&mut self, offset: usize, len: usize) -> Result<(), FlashError> {
+        check_erase(offset, len)?;
+        let from = (ADDR_OFFSET + offset) as u32;
+        self.flash
+            .blocking_erase(from, from + len as u32)
+            .map_err(|_| FlashError::Device)
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        check_storage(offset, data.len())?;
+        self.flash
+            .blocking_write((ADDR_OFFSET + offset) as u32, data)
+            .map_err(|_| FlashError::Device)
+    }
 }
@@ -0,0 +1,233 @@
+use super::{
+    check_erase, check_storage, Flash, FlashError, ADDR_OFFSET, FLASH_SIZE, STORAGE_OFFSET,
+    STORAGE_SIZE,
+};
+use alloc::boxed::Box;
+use alloc::vec;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+extern crate alloc;
+
+/// Value of erased flash
+pub const ERASED: u8 = 0xFF;
+
+/// Whole `FLASH_SIZE` flash chip in memory, erased unless an image is put in.
+///
+/// Offsets are relative to `ADDR_OFFSET` and follow the same rules as
+/// `FlashAccess`: reads must be word aligned and stay inside the chip,
+/// otherwise it panics the way the device does. Erase and write are limited
+/// to the storage region and programming only clears bits, like NOR flash.
+pub struct RamFlash {
+    memory: Vec<u8>,
+}
+
+impl RamFlash {
+    pub fn new() -> Self {
+        RamFlash {
+            memory: vec![ERASED; FLASH_SIZE],
+        }
+    }
+
+    /// Flash holding `image` at `ADDR_OFFSET`, as `probe-rs download --base-address` leaves it
+    pub fn with_image(image: &[u8]) -> Self {
+        let mut flash = RamFlash::new();
+        flash.put(ADDR_OFFSET, image);
+        flash
+    }
+
+    /// Copies `data` to the absolute flash `address`
+    pub fn put(&mut self, address: usize, data: &[u8]) {
+        assert!(
+            address + data.len() <= FLASH_SIZE,
+            "{} bytes at {:#x} don't fit in flash",
+            data.len(),
+            address
+        );
+        self.memory[address..address + data.len()].copy_from_slice(data);
+    }
+
+    /// Whole chip, from address 0
+    pub fn memory(&self) -> &[u8] {
+        &self.memory
+    }
+
+    fn read(&self, offset: usize, buf: &mut [u32]) {
+        let address = ADDR_OFFSET + offset;
+        let length = buf.len() * 4;
+        if length > FLASH_SIZE || address > FLASH_SIZE - length {
+            panic!(
+                "Flash read of {} bytes at {:#x}: OutOfBounds",
+                length, address
+            );
+        }
+        if address % 4 != 0 {
+            panic!("Flash read at {:#x}: Unaligned", address);
+        }
+
+        for (word, bytes) in buf.iter_mut().zip(self.memory[address..].chunks_exact(4)) {
+            *word = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+    }
+
+    fn erase_range(&mut self, offset: usize, len: usize) -> Result<(), FlashError> {
+        check_erase(offset, len)?;
+        let address = ADDR_OFFSET + offset;
+        self.memory[address..address + len].fill(ERASED);
+        Ok(())
+    }
+
+    fn program(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        check_storage(offset, data.len())?;
+        let address = ADDR_OFFSET + offset;
+        for (byte, value) in self.memory[address..].iter_mut().zip(data) {
+            *byte &= value;
+        }
+        Ok(())
+    }
+
+    fn storage(&self) -> &[u8] {
+        let address = ADDR_OFFSET + STORAGE_OFFSET;
+        &self.memory[address..address + STORAGE_SIZE]
+    }
+}
+
+impl Default for RamFlash {
+    fn default() -> Self {
+        RamFlash::new()
+    }
+}
+
+#[async_trait]
+impl Flash for RamFlash {
+    async fn load(&mut self, offset: usize, buf: &mut [u32]) {
+        self.read(offset, buf);
+    }
+
+    async fn load_tga<const SIZE_U32: usize, const SIZE_U8: usize>(
+        &mut self,
+        offset: usize,
+    ) -> Vec<u8> {
+        load_tga::<SIZE_U32, SIZE_U8>(&*self, offset)
+    }
+
+    async fn erase(&mut self, offset: usize, len: usize) -> Result<(), FlashError> {
+        self.erase_range(offset, len)
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.program(offset, data)
+    }
+}
+
+/// `RamFlash` filled from an image file, e.g. `levels.bin` or a dump of the whole chip.
+///
+/// The image is never written back. Erases and writes go to memory only,
+/// unless a storage file is attached with `with_storage`.
+pub struct FileFlash {
+    path: PathBuf,
+    storage: Option<PathBuf>,
+    flash: RamFlash,
+}
+
+impl FileFlash {
+    /// Image that is flashed at `ADDR_OFFSET`
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        FileFlash::open_at(path, ADDR_OFFSET)
+    }
+
+    /// Image that starts at the absolute flash `address`, 0 for a full chip dump
+    pub fn open_at<P: AsRef<Path>>(path: P, address: usize) -> io::Result<Self> {
+        let image = fs::read(&path)?;
+        if address + image.len() > FLASH_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "image doesn't fit in flash",
+            ));
+        }
+
+        let mut flash = RamFlash::new();
+        flash.put(address, &image);
+        Ok(FileFlash {
+            path: path.as_ref().to_path_buf(),
+            storage: None,
+            flash,
+        })
+    }
+
+    /// Keeps the storage region in the file at `path`, so saves survive a restart.
+    /// The file is loaded if it exists and rewritten after every erase and write
+    pub fn with_storage<P: AsRef<Path>>(mut self, path: P) -> io::Result<Self> {
+        match fs::read(&path) {
+            Ok(storage) if storage.len() > STORAGE_SIZE => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "storage file is bigger than the storage region",
+                ));
+            }
+            Ok(storage) => self.flash.put(ADDR_OFFSET + STORAGE_OFFSET, &storage),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => (),
+            Err(err) => return Err(err),
+        }
+        self.storage = Some(path.as_ref().to_path_buf());
+        Ok(self)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn storage_path(&self) -> Option<&Path> {
+        self.storage.as_deref()
+    }
+
+    pub fn memory(&self) -> &[u8] {
+        self.flash.memory()
+    }
+
+    fn persist(&self) -> Result<(), FlashError> {
+        match &self.storage {
+            Some(path) => fs::write(path, self.flash.storage()).map_err(|_| FlashError::Device),
+            None => Ok(()),
+        }
+    }
+}
+
+#[async_trait]
+impl Flash for FileFlash {
+    async fn load(&mut self, offset: usize, buf: &mut [u32]) {
+        self.flash.read(offset, buf);
+    }
+
+    async fn load_tga<const SIZE_U32: usize, const SIZE_U8: usize>(
+        &mut self,
+        offset: usize,
+    ) -> Vec<u8> {
+        load_tga::<SIZE_U32, SIZE_U8>(&self.flash, offset)
+    }
+
+    async fn erase(&mut self, offset: usize, len: usize) -> Result<(), FlashError> {
+        self.flash.erase_range(offset, len)?;
+        self.persist()
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.flash.program(offset, data)?;
+        self.persist()
+    }
+}
+
+fn load_tga<const SIZE_U32: usize, const SIZE_U8: usize>(
+    flash: &RamFlash,
+    offset: usize,
+) -> Vec<u8> {
+    assert!(SIZE_U32 == SIZE_U8 / 4 + 1);
+    let mut data = [0u32; SIZE_U32];
+    flash.read(offset, &mut data);
+    data.iter()
+        .flat_map(|word| word.to_le_bytes())
+        .take(SIZE_U8)
+        .collect()
+}
@@ -0,0 +1,374 @@
+//! Recording of the player's input and its replay.
+//!
+//! A recording starts when a level is entered and holds every event the
+//! `StateMachine` got from then on:
+//!
+//! | offset        | size | field                                  |
+//! |---------------|------|----------------------------------------|
+//! | 0             | 4    | magic `KREC`                           |
+//! | 4             | 1    | format version                         |
+//! | 5             | 1    | level index                            |
+//! | 6             | 2    | entries length, little endian          |
+//! | 8             | 4    | value of the first tick, little endian |
+//! | 12            | 4    | hash of the level, little endian       |
+//! | `HEADER_SIZE` | ...  | entries                                |
+//!
+//! An entry is the number of ticks since the previous entry as an LEB128
+//! varint followed by a button code. Code `END` closes the recording, its
+//! ticks are the ones that came after the last button.
+//!
+//! The level hash is `Grid::content_hash` of the level as it was loaded, a
+//! recording made on a level that changed since is rejected before it plays.
+
+use crate::game::events::{Buttons, Event, States};
+use crate::game::flash::Flash;
+use alloc::vec::Vec;
+use core::fmt;
+use heapless::Vec as FixedVec;
+extern crate alloc;
+
+pub const MAGIC: [u8; 4] = *b"KREC";
+/// Bump whenever the format or the game rules change, old recordings won't play the same
+pub const VERSION: u8 = 3;
+
+pub const HEADER_SIZE: usize = 16;
+pub const MAX_ENTRIES_SIZE: usize = 4096;
+
+const END: u8 = 0;
+
+pub struct Recorder {
+    level: Option<usize>,
+    level_hash: u32,
+    active: bool,
+    first_tick: Option<u32>,
+    ticks: u32,
+    entries: FixedVec<u8, MAX_ENTRIES_SIZE>,
+    full: bool,
+}
+
+impl Recorder {
+    pub fn new() -> Self {
+        Recorder {
+            level: None,
+            level_hash: 0,
+            active: false,
+            first_tick: None,
+            ticks: 0,
+            entries: FixedVec::new(),
+            full: false,
+        }
+    }
+
+    /// Called on every state change. Entering a different level starts a new
+    /// recording, leaving the levels stops it and keeps what was recorded.
+    /// Returns `true` when a new recording started, its level hash is to be set then
+    pub fn enter(&mut self, level: Option<usize>) -> bool {
+        match level {
+            Some(level) if !self.active || self.level != Some(level) => {
+                self.level = Some(level);
+                self.level_hash = 0;
+                self.active = true;
+                self.first_tick = None;
+                self.ticks = 0;
+                self.entries.clear();
+                self.full = false;
+                true
+            }
+            Some(_) => false,
+            None => {
+                self.active = false;
+                false
+            }
+        }
+    }
+
+    /// Hash of the level being recorded, see `Grid::content_hash`
+    pub fn set_level_hash(&mut self, hash: u32) {
+        self.level_hash = hash;
+    }
+
+    pub fn record(&mut self, event: &Event) {
+        if !self.active || self.full {
+            return;
+        }
+
+        match event {
+            Event::Tick(tick) => {
+                self.first_tick.get_or_insert(*tick as u32);
+                self.ticks += 1;
+            }
+            Event::Button(button) => {
+                let mut entry: FixedVec<u8, 6> = FixedVec::new();
+                write_varint(&mut entry, self.ticks);
+                entry.push(button_code(button)).unwrap();
+
+                // Leave room for the end marker
+                if self.entries.len() + entry.len() + 6 > MAX_ENTRIES_SIZE {
+                    self.full = true;
+                    return;
+                }
+                self.entries.extend_from_slice(&entry).unwrap();
+                self.ticks = 0;
+            }
+        }
+    }
+
+    /// `true` once the buffer ran out, events after that are not recorded
+    pub fn is_full(&self) -> bool {
+        self.full
+    }
+
+    /// The recording so far, `None` if no level was entered yet
+    pub fn encode(&self) -> Option<Vec<u8>> {
+        let level = self.level?;
+
+        let mut data = Vec::with_capacity(HEADER_SIZE + self.entries.len() + 6);
+        data.extend_from_slice(&MAGIC);
+        data.push(VERSION);
+        data.push(level as u8);
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&self.first_tick.unwrap_or(0).to_le_bytes());
+        data.extend_from_slice(&self.level_hash.to_le_bytes());
+        data.extend_from_slice(&self.entries);
+
+        let mut end: FixedVec<u8, 6> = FixedVec::new();
+        write_varint(&mut end, self.ticks);
+        end.push(END).unwrap();
+        data.extend_from_slice(&end);
+
+        let len = (data.len() - HEADER_SIZE) as u16;
+        data[6..8].copy_from_slice(&len.to_le_bytes());
+        Some(data)
+    }
+}
+
+impl Default for Recorder {
+    fn default() -> Self {
+        Recorder::new()
+    }
+}
+
+pub struct Replay {
+    level: usize,
+    level_hash: u32,
+    first_tick: u32,
+    entries: Vec<u8>,
+}
+
+impl Replay {
+    pub fn decode(data: &[u8]) -> Result<Self, ReplayError> {
+        if data.len() < HEADER_SIZE {
+            return Err(ReplayError::TooShort(data.len()));
+        }
+        if data[0..4] != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(ReplayError::UnsupportedVersion(data[4]));
+        }
+
+        let len = u16::from_le_bytes([data[6], data[7]]) as usize;
+        if len > MAX_ENTRIES_SIZE + 6 {
+            return Err(ReplayError::TooLong(len));
+        }
+        if data.len() < HEADER_SIZE + len {
+            return Err(ReplayError::TooShort(data.len()));
+        }
+
+        let replay = Replay {
+            level: data[5] as usize,
+            first_tick: u32::from_le_bytes([data[8], data[9], data[10], data[11]]),
+            level_hash: u32::from_le_bytes([data[12], data[13], data[14], data[15]]),
+            entries: Vec::from(&data[HEADER_SIZE..HEADER_SIZE + len]),
+        };
+        replay.check()?;
+        Ok(replay)
+    }
+
+    pub async fn load<F: Flash>(flash: &mut F, offset: usize) -> Result<Self, ReplayError> {
+        let header = &mut [0u32; HEADER_SIZE / 4];
+        flash.load(offset, header).await;
+        let header = words_to_bytes(header);
+        if header[0..4] != MAGIC {
+            return Err(ReplayError::BadMagic);
+        }
+
+        let len = u16::from_le_bytes([header[6], header[7]]) as usize;
+        if len > MAX_ENTRIES_SIZE + 6 {
+            return Err(ReplayError::TooLong(len));
+        }
+
+        let mut data = alloc::vec![0u32; (HEADER_SIZE + len).div_ceil(4)];
+        flash.load(offset, &mut data).await;
+        Replay::decode(&words_to_bytes(&data))
+    }
+
+    pub fn level(&self) -> usize {
+        self.level
+    }
+
+    /// Hash of the level the recording was made on
+    pub fn level_hash(&self) -> u32 {
+        self.level_hash
+    }
+
+    /// Events in the order they were recorded, ticks included
+    pub fn events(&self) -> Events<'_> {
+        Events {
+            entries: &self.entries,
+            position: 0,
+            tick: self.first_tick as u128,
+            ticks: 0,
+            button: None,
+        }
+    }
+
+    /// Walks all the entries so a broken recording fails on load, not halfway through
+    fn check(&self) -> Result<(), ReplayError> {
+        let mut position = 0;
+        loop {
+            let (_, len) =
+                read_varint(&self.entries[position..]).ok_or(ReplayError::UnexpectedEnd)?;
+            position += len;
+
+            let code = *self
+                .entries
+                .get(position)
+                .ok_or(ReplayError::UnexpectedEnd)?;
+            if code == END {
+                return Ok(());
+            }
+            code_button(code).ok_or(ReplayError::BadEntry(HEADER_SIZE + position))?;
+            position += 1;
+        }
+    }
+}
+
+pub struct Events<'a> {
+    entries: &'a [u8],
+    position: usize,
+    tick: u128,
+    ticks: u32,
+    button: Option<u8>,
+}
+
+impl Iterator for Events<'_> {
+    type Item = Event;
+
+    fn next(&mut self) -> Option<Event> {
+        loop {
+            if self.ticks > 0 {
+                self.ticks -= 1;
+                let tick = self.tick;
+                self.tick = self.tick.wrapping_add(1);
+                return Some(Event::Tick(tick));
+            }
+
+            if let Some(code) = self.button.take() {
+                return code_button(code).map(Event::Button);
+            }
+
+            // Entries were checked on decode
+            let (ticks, len) = read_varint(&self.entries[self.position..])?;
+            let code = *self.entries.get(self.position + len)?;
+            self.position += len + 1;
+            self.ticks = ticks;
+            match code {
+                END => self.position = self.entries.len(),
+                code => self.button = Some(code),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    TooShort(usize),
+    TooLong(usize),
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadEntry(usize),
+    UnexpectedEnd,
+    /// The level changed since the recording was made, or is gone
+    StaleLevel(usize),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::TooShort(len) => write!(f, "Recording too short: {} bytes", len),
+            ReplayError::TooLong(len) => write!(f, "Recording too long: {} bytes", len),
+            ReplayError::BadMagic => write!(f, "Not a recording"),
+            ReplayError::UnsupportedVersion(version) => {
+                write!(
+                    f,
+                    "Recording version {} is stale, {} expected",
+                    version, VERSION
+                )
+            }
+            ReplayError::BadEntry(offset) => write!(f, "Bad entry at byte {}", offset),
+            ReplayError::UnexpectedEnd => write!(f, "Recording ends without end marker"),
+            ReplayError::StaleLevel(level) => {
+                write!(f, "Level {} changed since it was recorded", level + 1)
+            }
+        }
+    }
+}
+
+fn button_code(button: &Buttons) -> u8 {
+    let (index, state) = match button {
+        Buttons::Up(state) => (0, state),
+        Buttons::Down(state) => (1, state),
+        Buttons::Left(state) => (2, state),
+        Buttons::Right(state) => (3, state),
+        Buttons::Reset(state) => (4, state),
+    };
+    let released = match state {
+        States::Pressed => 0,
+        States::Released => 1,
+    };
+    1 + index * 2 + released
+}
+
+fn code_button(code: u8) -> Option<Buttons> {
+    let state = match code % 2 {
+        1 => States::Pressed,
+        _ => States::Released,
+    };
+    match code {
+        1 | 2 => Some(Buttons::Up(state)),
+        3 | 4 => Some(Buttons::Down(state)),
+        5 | 6 => Some(Buttons::Left(state)),
+        7 | 8 => Some(Buttons::Right(state)),
+        9 | 10 => Some(Buttons::Reset(state)),
+        _ => None,
+    }
+}
+
+fn write_varint<const N: usize>(buf: &mut FixedVec<u8, N>, mut value: u32) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            buf.push(byte).unwrap();
+            return;
+        }
+        buf.push(byte | 0x80).unwrap();
+    }
+}
+
+/// Value and its length in bytes
+fn read_varint(data: &[u8]) -> Option<(u32, usize)> {
+    let mut value: u32 = 0;
+    for (i, byte) in data.iter().take(5).enumerate() {
+        value |= ((byte & 0x7F) as u32) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn words_to_bytes(words: &[u32]) -> Vec<u8> {
+    words.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
@@ -0,0 +1,347 @@
+//! Player progress and settings kept in the flash storage region.
+//!
+//! Saves are appended as fixed `RECORD_SIZE` slots to `SAVE_SECTORS` sectors
+//! at `SAVE_OFFSET`. A new save goes to the slot after the newest one, a sector
+//! is erased only when the writes wrap into it, so the sectors wear evenly.
+//!
+//! | offset        | size | field                                        |
+//! |---------------|------|----------------------------------------------|
+//! | 0             | 4    | magic `KSAV`                                 |
+//! | 4             | 1    | format version                               |
+//! | 5             | 1    | reserved, 0                                  |
+//! | 6             | 2    | payload length, little endian                |
+//! | 8             | 4    | sequence number, little endian               |
+//! | `HEADER_SIZE` | ...  | payload                                      |
+//! | ...           | 4    | CRC-32 of everything above, little endian    |
+//!
+//! The record with the highest sequence number that passes the checks wins.
+//! Torn, corrupted or other-version records are skipped, and with none left
+//! the game starts from `SaveData::default()`.
+
+use crate::game::flash::{Flash, FlashError, SECTOR_SIZE, STORAGE_OFFSET};
+use crate::game::settings::{Settings, SETTINGS_SIZE};
+use crate::logging::{info, warn, Display2Format};
+use alloc::vec::Vec;
+use core::fmt;
+extern crate alloc;
+
+pub const MAGIC: [u8; 4] = *b"KSAV";
+/// Bump whenever the payload changes, records of other versions are ignored
+pub const VERSION: u8 = 1;
+
+pub const SAVE_OFFSET: usize = STORAGE_OFFSET; // Relative to ADDR_OFFSET
+pub const SAVE_SECTORS: usize = 4;
+pub const RECORD_SIZE: usize = 256;
+pub const RECORDS_PER_SECTOR: usize = SECTOR_SIZE / RECORD_SIZE;
+pub const SLOTS: usize = SAVE_SECTORS * RECORDS_PER_SECTOR;
+
+pub const HEADER_SIZE: usize = 12;
+pub const CRC_SIZE: usize = 4;
+
+/// Levels the progress is kept for
+pub const MAX_LEVELS: usize = 32;
+const NO_MOVES: u16 = u16::MAX;
+
+pub const PAYLOAD_SIZE: usize = 1 + 4 + MAX_LEVELS * 2 + SETTINGS_SIZE;
+
+const _: () = assert!(HEADER_SIZE + PAYLOAD_SIZE + CRC_SIZE <= RECORD_SIZE);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Progress {
+    unlocked: usize,
+    completed: u32,
+    best_moves: [u16; MAX_LEVELS],
+}
+
+impl Progress {
+    pub fn new() -> Self {
+        Progress {
+            unlocked: 1,
+            completed: 0,
+            best_moves: [NO_MOVES; MAX_LEVELS],
+        }
+    }
+
+    /// Number of unlocked levels, the first one is always open
+    pub fn unlocked(&self) -> usize {
+        self.unlocked
+    }
+
+    /// `true` once a level was won, a save made for the settings alone has no progress
+    pub fn has_progress(&self) -> bool {
+        self.completed != 0
+    }
+
+    pub fn is_unlocked(&self, index: usize) -> bool {
+        index < self.unlocked
+    }
+
+    pub fn is_completed(&self, index: usize) -> bool {
+        index < MAX_LEVELS && self.completed & (1 << index) != 0
+    }
+
+    /// Fewest moves the level was won with
+    pub fn best_moves(&self, index: usize) -> Option<u16> {
+        match self.best_moves.get(index) {
+            Some(&NO_MOVES) | None => None,
+            Some(&moves) => Some(moves),
+        }
+    }
+
+    /// Marks the level won and unlocks the next one
+    pub fn complete(&mut self, index: usize, moves: u16) {
+        if index >= MAX_LEVELS {
+            return;
+        }
+        self.completed |= 1 << index;
+        let moves = moves.min(NO_MOVES - 1);
+        match self.best_moves(index) {
+            Some(best) if best <= moves => (),
+            _ => self.best_moves[index] = moves,
+        }
+        self.unlocked = self.unlocked.max((index + 2).min(MAX_LEVELS));
+    }
+}
+
+impl Default for Progress {
+    fn default() -> Self {
+        Progress::new()
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct SaveData {
+    pub progress: Progress,
+    pub settings: Settings,
+}
+
+impl SaveData {
+    pub fn encode(&self) -> [u8; PAYLOAD_SIZE] {
+        let mut data = [0u8; PAYLOAD_SIZE];
+        data[0] = self.progress.unlocked as u8;
+        data[1..5].copy_from_slice(&self.progress.completed.to_le_bytes());
+        for (chunk, moves) in data[5..5 + MAX_LEVELS * 2]
+            .chunks_exact_mut(2)
+            .zip(self.progress.best_moves)
+        {
+            chunk.copy_from_slice(&moves.to_le_bytes());
+        }
+        data[5 + MAX_LEVELS * 2..].copy_from_slice(&self.settings.encode());
+        data
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, SaveError> {
+        if data.len() != PAYLOAD_SIZE {
+            return Err(SaveError::BadLength(data.len()));
+        }
+
+        let unlocked = data[0] as usize;
+        if unlocked == 0 || unlocked > MAX_LEVELS {
+            return Err(SaveError::BadProgress);
+        }
+
+        let mut best_moves = [NO_MOVES; MAX_LEVELS];
+        for (moves, chunk) in best_moves
+            .iter_mut()
+            .zip(data[5..5 + MAX_LEVELS * 2].chunks_exact(2))
+        {
+            *moves = u16::from_le_bytes([chunk[0], chunk[1]]);
+        }
+
+        let mut settings = [0u8; SETTINGS_SIZE];
+        settings.copy_from_slice(&data[5 + MAX_LEVELS * 2..]);
+
+        Ok(SaveData {
+            progress: Progress {
+                unlocked,
+                completed: u32::from_le_bytes([data[1], data[2], data[3], data[4]]),
+                best_moves,
+            },
+            settings: Settings::decode(&settings),
+        })
+    }
+}
+
+/// Newest save in flash and where the next one goes
+pub struct SaveSlots {
+    data: Option<SaveData>,
+    sequence: u32,
+    next: usize,
+}
+
+impl SaveSlots {
+    /// Scans all the slots. Never fails, broken records are logged and skipped
+    pub async fn load<F: Flash>(flash: &mut F) -> Self {
+        let mut slots = SaveSlots {
+            data: None,
+            sequence: 0,
+            next: 0,
+        };
+
+        for slot in 0..SLOTS {
+            let record = read_slot(flash, slot).await;
+            match decode_record(&record) {
+                Ok((sequence, data)) => {
+                    if slots.data.is_none() || sequence > slots.sequence {
+                        slots.data = Some(data);
+                        slots.sequence = sequence;
+                        slots.next = (slot + 1) % SLOTS;
+                    }
+                }
+                Err(SaveError::Erased) => (),
+                Err(err) => warn!("Save slot {} skipped: {}", slot, Display2Format(&err)),
+            }
+        }
+
+        match slots.data {
+            Some(_) => info!("Save {} loaded", slots.sequence),
+            None => info!("No save found"),
+        }
+        slots
+    }
+
+    pub fn data(&self) -> Option<&SaveData> {
+        self.data.as_ref()
+    }
+
+    /// Sequence number of the newest save, 0 when there is none
+    pub fn sequence(&self) -> u32 {
+        self.sequence
+    }
+
+    pub async fn store<F: Flash>(
+        &mut self,
+        flash: &mut F,
+        data: &SaveData,
+    ) -> Result<(), SaveError> {
+        let mut slot = self.next;
+        // Leftovers of a torn write, the rest of the sector can't be trusted
+        if slot % RECORDS_PER_SECTOR != 0 && !is_erased(&read_slot(flash, slot).await) {
+            slot = (slot / RECORDS_PER_SECTOR + 1) % SAVE_SECTORS * RECORDS_PER_SECTOR;
+        }
+        if slot % RECORDS_PER_SECTOR == 0 {
+            flash.erase(slot_offset(slot), SECTOR_SIZE).await?;
+        }
+
+        let sequence = self.sequence.wrapping_add(1);
+        flash
+            .write(slot_offset(slot), &encode_record(sequence, data))
+            .await?;
+
+        self.data = Some(data.clone());
+        self.sequence = sequence;
+        self.next = (slot + 1) % SLOTS;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum SaveError {
+    Erased,
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadLength(usize),
+    BadCrc,
+    BadProgress,
+    Flash(FlashError),
+}
+
+impl From<FlashError> for SaveError {
+    fn from(err: FlashError) -> Self {
+        SaveError::Flash(err)
+    }
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Erased => write!(f, "Slot is empty"),
+            SaveError::BadMagic => write!(f, "Not a save"),
+            SaveError::UnsupportedVersion(version) => {
+                write!(f, "Save version {} is stale, {} expected", version, VERSION)
+            }
+            SaveError::BadLength(len) => write!(f, "Bad payload length {}", len),
+            SaveError::BadCrc => write!(f, "CRC mismatch"),
+            SaveError::BadProgress => write!(f, "Bad progress"),
+            SaveError::Flash(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub fn encode_record(sequence: u32, data: &SaveData) -> Vec<u8> {
+    let mut record = Vec::with_capacity(HEADER_SIZE + PAYLOAD_SIZE + CRC_SIZE);
+    record.extend_from_slice(&MAGIC);
+    record.push(VERSION);
+    record.push(0);
+    record.extend_from_slice(&(PAYLOAD_SIZE as u16).to_le_bytes());
+    record.extend_from_slice(&sequence.to_le_bytes());
+    record.extend_from_slice(&data.encode());
+    let crc = crc32(&record);
+    record.extend_from_slice(&crc.to_le_bytes());
+    record
+}
+
+/// Sequence number and data of a record
+pub fn decode_record(record: &[u8]) -> Result<(u32, SaveData), SaveError> {
+    if is_erased(record) {
+        return Err(SaveError::Erased);
+    }
+    if record.len() < HEADER_SIZE + CRC_SIZE {
+        return Err(SaveError::BadLength(record.len()));
+    }
+    if record[0..4] != MAGIC {
+        return Err(SaveError::BadMagic);
+    }
+    if record[4] != VERSION {
+        return Err(SaveError::UnsupportedVersion(record[4]));
+    }
+
+    let len = u16::from_le_bytes([record[6], record[7]]) as usize;
+    if HEADER_SIZE + len + CRC_SIZE > record.len() {
+        return Err(SaveError::BadLength(len));
+    }
+    let end = HEADER_SIZE + len;
+    let crc = u32::from_le_bytes([
+        record[end],
+        record[end + 1],
+        record[end + 2],
+        record[end + 3],
+    ]);
+    if crc != crc32(&record[..end]) {
+        return Err(SaveError::BadCrc);
+    }
+
+    let sequence = u32::from_le_bytes([record[8], record[9], record[10], record[11]]);
+    Ok((sequence, SaveData::decode(&record[HEADER_SIZE..end])?))
+}
+
+/// Relative to ADDR_OFFSET
+pub fn slot_offset(slot: usize) -> usize {
+    SAVE_OFFSET + slot * RECORD_SIZE
+}
+
+async fn read_slot<F: Flash>(flash: &mut F, slot: usize) -> Vec<u8> {
+    let data = &mut [0u32; RECORD_SIZE / 4];
+    flash.load(slot_offset(slot), data).await;
+    data.iter().flat_map(|word| word.to_le_bytes()).collect()
+}
+
+fn is_erased(record: &[u8]) -> bool {
+    record.iter().all(|&byte| byte == 0xFF)
+}
+
+/// CRC-32/ISO-HDLC, the one zlib and PNG use
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = match crc & 1 {
+                1 => (crc >> 1) ^ 0xEDB8_8320,
+                _ => crc >> 1,
+            };
+        }
+    }
+    !crc
+}
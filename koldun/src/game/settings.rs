@@ -0,0 +1,302 @@
+//! Player settings, edited on the options screen and kept in the save.
+//!
+//! The settings in effect live in a global so the button and timer tasks see
+//! them too. `Initial` loads them from flash before the start menu is shown.
+
+use crate::game::colors::{Palette, DUNGEON, FOREST, ICE};
+use crate::game::flash::Flash;
+use crate::game::save::SaveSlots;
+use crate::ili9486::Display;
+use core::sync::atomic::{AtomicU8, Ordering};
+
+pub const SETTINGS_SIZE: usize = 8;
+
+/// A setting with a fixed set of values, cycled through on the options screen
+pub trait Choice: Copy + Sized + 'static {
+    const ALL: &'static [Self];
+
+    fn name(&self, language: Language) -> &'static str;
+
+    fn index(&self) -> usize;
+
+    fn from_index(index: usize) -> Option<Self> {
+        Self::ALL.get(index).copied()
+    }
+
+    fn next(&self) -> Self {
+        Self::ALL[(self.index() + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum Theme {
+    Dungeon,
+    Forest,
+    Ice,
+}
+
+impl Theme {
+    pub fn palette(&self) -> &'static Palette {
+        match self {
+            Theme::Dungeon => &DUNGEON,
+            Theme::Forest => &FOREST,
+            Theme::Ice => &ICE,
+        }
+    }
+}
+
+impl Choice for Theme {
+    const ALL: &'static [Self] = &[Theme::Dungeon, Theme::Forest, Theme::Ice];
+
+    fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (Theme::Dungeon, Language::English) => "Dungeon",
+            (Theme::Dungeon, Language::Russian) => "Подземелье",
+            (Theme::Forest, Language::English) => "Forest",
+            (Theme::Forest, Language::Russian) => "Лес",
+            (Theme::Ice, Language::English) => "Ice",
+            (Theme::Ice, Language::Russian) => "Лед",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// Rate of the game ticks, all the animations are counted in ticks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum AnimationSpeed {
+    Slow,
+    Normal,
+    Fast,
+}
+
+impl AnimationSpeed {
+    pub fn tick_hz(&self) -> u64 {
+        match self {
+            AnimationSpeed::Slow => 7,
+            AnimationSpeed::Normal => 10,
+            AnimationSpeed::Fast => 15,
+        }
+    }
+}
+
+impl Choice for AnimationSpeed {
+    const ALL: &'static [Self] = &[
+        AnimationSpeed::Slow,
+        AnimationSpeed::Normal,
+        AnimationSpeed::Fast,
+    ];
+
+    fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (AnimationSpeed::Slow, Language::English) => "Slow",
+            (AnimationSpeed::Slow, Language::Russian) => "Медленно",
+            (AnimationSpeed::Normal, Language::English) => "Normal",
+            (AnimationSpeed::Normal, Language::Russian) => "Обычно",
+            (AnimationSpeed::Fast, Language::English) => "Fast",
+            (AnimationSpeed::Fast, Language::Russian) => "Быстро",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// How a held direction button repeats its press
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum RepeatRate {
+    Off,
+    Slow,
+    Fast,
+}
+
+impl RepeatRate {
+    /// Milliseconds between repeated presses, `None` when off
+    pub fn interval_ms(&self) -> Option<u64> {
+        match self {
+            RepeatRate::Off => None,
+            RepeatRate::Slow => Some(400),
+            RepeatRate::Fast => Some(200),
+        }
+    }
+}
+
+impl Choice for RepeatRate {
+    const ALL: &'static [Self] = &[RepeatRate::Off, RepeatRate::Slow, RepeatRate::Fast];
+
+    fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (RepeatRate::Off, Language::English) => "Off",
+            (RepeatRate::Off, Language::Russian) => "Выкл",
+            (RepeatRate::Slow, Language::English) => "Slow",
+            (RepeatRate::Slow, Language::Russian) => "Медленно",
+            (RepeatRate::Fast, Language::English) => "Fast",
+            (RepeatRate::Fast, Language::Russian) => "Быстро",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum Brightness {
+    Low,
+    Medium,
+    High,
+}
+
+impl Brightness {
+    /// Parameter of the display's `WriteDisplayBrightnessValue`
+    pub fn value(&self) -> u8 {
+        match self {
+            Brightness::Low => 0x40,
+            Brightness::Medium => 0xA0,
+            Brightness::High => 0xFF,
+        }
+    }
+}
+
+impl Choice for Brightness {
+    const ALL: &'static [Self] = &[Brightness::Low, Brightness::Medium, Brightness::High];
+
+    fn name(&self, language: Language) -> &'static str {
+        match (self, language) {
+            (Brightness::Low, Language::English) => "Low",
+            (Brightness::Low, Language::Russian) => "Низкая",
+            (Brightness::Medium, Language::English) => "Medium",
+            (Brightness::Medium, Language::Russian) => "Средняя",
+            (Brightness::High, Language::English) => "High",
+            (Brightness::High, Language::Russian) => "Высокая",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub enum Language {
+    English,
+    Russian,
+}
+
+impl Choice for Language {
+    const ALL: &'static [Self] = &[Language::English, Language::Russian];
+
+    fn name(&self, _language: Language) -> &'static str {
+        // Always in its own language, so it can be found in any
+        match self {
+            Language::English => "English",
+            Language::Russian => "Русский",
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub struct Settings {
+    pub theme: Theme,
+    pub animation_speed: AnimationSpeed,
+    pub repeat_rate: RepeatRate,
+    pub brightness: Brightness,
+    pub language: Language,
+}
+
+impl Settings {
+    pub const DEFAULT: Settings = Settings {
+        theme: Theme::Dungeon,
+        animation_speed: AnimationSpeed::Normal,
+        repeat_rate: RepeatRate::Off,
+        brightness: Brightness::High,
+        language: Language::English,
+    };
+
+    pub const fn encode(&self) -> [u8; SETTINGS_SIZE] {
+        [
+            self.theme as u8,
+            self.animation_speed as u8,
+            self.repeat_rate as u8,
+            self.brightness as u8,
+            self.language as u8,
+            0,
+            0,
+            0,
+        ]
+    }
+
+    /// Unknown values fall back to their defaults, so a newer save still loads
+    pub fn decode(data: &[u8; SETTINGS_SIZE]) -> Self {
+        let default = Settings::DEFAULT;
+        Settings {
+            theme: Theme::from_index(data[0] as usize).unwrap_or(default.theme),
+            animation_speed: AnimationSpeed::from_index(data[1] as usize)
+                .unwrap_or(default.animation_speed),
+            repeat_rate: RepeatRate::from_index(data[2] as usize).unwrap_or(default.repeat_rate),
+            brightness: Brightness::from_index(data[3] as usize).unwrap_or(default.brightness),
+            language: Language::from_index(data[4] as usize).unwrap_or(default.language),
+        }
+    }
+
+    /// Settings of the newest save, defaults without one
+    pub async fn load<F: Flash>(flash: &mut F) -> Self {
+        match SaveSlots::load(flash).await.data() {
+            Some(data) => data.settings,
+            None => Settings::DEFAULT,
+        }
+    }
+
+    /// Makes the settings current and sends the display its part of them
+    pub async fn apply<D: Display<u8>>(&self, display: &mut D) {
+        set_current(self);
+        display.set_brightness(self.brightness.value()).await;
+    }
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings::DEFAULT
+    }
+}
+
+const DEFAULT: [u8; SETTINGS_SIZE] = Settings::DEFAULT.encode();
+
+static CURRENT: [AtomicU8; SETTINGS_SIZE] = [
+    AtomicU8::new(DEFAULT[0]),
+    AtomicU8::new(DEFAULT[1]),
+    AtomicU8::new(DEFAULT[2]),
+    AtomicU8::new(DEFAULT[3]),
+    AtomicU8::new(DEFAULT[4]),
+    AtomicU8::new(DEFAULT[5]),
+    AtomicU8::new(DEFAULT[6]),
+    AtomicU8::new(DEFAULT[7]),
+];
+
+/// Settings in effect
+pub fn current() -> Settings {
+    let mut data = [0u8; SETTINGS_SIZE];
+    for (byte, value) in data.iter_mut().zip(CURRENT.iter()) {
+        *byte = value.load(Ordering::Relaxed);
+    }
+    Settings::decode(&data)
+}
+
+pub fn set_current(settings: &Settings) {
+    for (value, byte) in CURRENT.iter().zip(settings.encode()) {
+        value.store(byte, Ordering::Relaxed);
+    }
+}
@@ -1,10 +1,14 @@
 use crate::game::events::Event;
 use crate::game::flash::Flash;
+use crate::game::replay::{Recorder, Replay, ReplayError};
 use crate::game::state_mashine::states::initial::Initial;
+use crate::game::state_mashine::states::level::{level_hash, Level};
 use crate::game::state_mashine::states::State;
+use crate::game::tile_cache::{TileCache, DEFAULT_BUDGET};
 use crate::ili9486::Display;
 use crate::ili9486::GameDisplay;
 use alloc::boxed::Box;
+use core::future::{ready, Future};
 use core::marker::Send;
 use embedded_graphics::pixelcolor::Rgb565;
 extern crate alloc;
@@ -15,6 +19,9 @@ pub struct StateMachine<D: GameDisplay, F: Flash> {
     state: Box<dyn State<D, F>>,
     display: D,
     flash: F,
+    /// Rendered tiles, kept from one state to the next
+    tiles: TileCache,
+    recorder: Option<Recorder>,
 }
 
 impl<D, F> StateMachine<D, F>
@@ -29,13 +36,104 @@ where
             state,
             display,
             flash,
+            tiles: TileCache::new(DEFAULT_BUDGET),
+            recorder: None,
         }
     }
 
+    pub fn display(&self) -> &D {
+        &self.display
+    }
+
+    pub fn flash(&self) -> &F {
+        &self.flash
+    }
+
+    pub fn tiles(&self) -> &TileCache {
+        &self.tiles
+    }
+
+    /// The tile cache, to change its budget
+    pub fn tiles_mut(&mut self) -> &mut TileCache {
+        &mut self.tiles
+    }
+
+    /// Index of the level the current state belongs to, `None` in the menus
+    pub fn level(&self) -> Option<usize> {
+        self.state.level()
+    }
+
+    /// Starts recording input, the recording begins with the next level entered
+    pub fn record(&mut self) {
+        self.recorder = Some(Recorder::new());
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
     pub async fn on_control(&mut self, event: Event) {
-        if let Some(state) = self.state.on_event(event, &mut self.display).await {
-            self.state = state;
-            self.state.on_init(&mut self.display, &mut self.flash).await;
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(&event);
+        }
+
+        if let Some(state) = self
+            .state
+            .on_event(event, &mut self.display, &mut self.flash, &mut self.tiles)
+            .await
+        {
+            self.switch(state).await;
+        }
+    }
+
+    /// Jumps straight into a level, as picking it in the menu would
+    pub async fn enter_level(&mut self, index: usize) {
+        self.switch(Box::new(Level::new(index))).await;
+    }
+
+    /// Plays a recording from the start of its level, all at once
+    pub async fn replay(&mut self, replay: &Replay) -> Result<(), ReplayError> {
+        self.replay_paced(replay, || ready(())).await
+    }
+
+    /// Plays a recording from the start of its level, awaiting `wait` before every tick
+    /// so the events come as far apart as they were recorded
+    pub async fn replay_paced<W>(
+        &mut self,
+        replay: &Replay,
+        mut wait: impl FnMut() -> W,
+    ) -> Result<(), ReplayError>
+    where
+        W: Future<Output = ()>,
+    {
+        self.check_replay(replay).await?;
+        self.enter_level(replay.level()).await;
+        for event in replay.events() {
+            if let Event::Tick(_) = event {
+                wait().await;
+            }
+            self.on_control(event).await;
+        }
+        Ok(())
+    }
+
+    /// Fails if the level of the recording is not the one it was made on
+    pub async fn check_replay(&mut self, replay: &Replay) -> Result<(), ReplayError> {
+        match level_hash(&mut self.flash, replay.level()).await {
+            Some(hash) if hash == replay.level_hash() => Ok(()),
+            _ => Err(ReplayError::StaleLevel(replay.level())),
+        }
+    }
+
+    async fn switch(&mut self, state: Box<dyn State<D, F>>) {
+        self.state = state;
+        if let Some(recorder) = &mut self.recorder {
+            if let (true, Some(level)) = (recorder.enter(self.state.level()), self.state.level()) {
+                recorder.set_level_hash(level_hash(&mut self.flash, level).await.unwrap_or(0));
+            }
         }
+        self.state
+            .on_init(&mut self.display, &mut self.flash, &mut self.tiles)
+            .await;
     }
 }
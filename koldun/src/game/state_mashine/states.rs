@@ -1,4 +1,4 @@
-use crate::game::{events::Event, flash::Flash};
+use crate::game::{events::Event, flash::Flash, tile_cache::TileCache};
 use crate::ili9486::GameDisplay;
 use alloc::boxed::Box;
 use async_trait::async_trait;
@@ -6,12 +6,25 @@ extern crate alloc;
 
 pub mod initial;
 pub mod level;
+pub mod level_select;
+pub mod options;
 pub mod spell;
 pub mod start_menu;
 
 #[async_trait]
 pub trait State<D: GameDisplay, F: Flash> {
-    async fn on_event(&mut self, event: Event, display: &mut D) -> Option<Box<dyn State<D, F>>>;
+    async fn on_event(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        flash: &mut F,
+        tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>>;
 
-    async fn on_init(&mut self, display: &mut D, flash: &mut F);
+    async fn on_init(&mut self, display: &mut D, flash: &mut F, tiles: &mut TileCache);
+
+    /// Index of the level the state belongs to, used to tell recordings apart
+    fn level(&self) -> Option<usize> {
+        None
+    }
 }
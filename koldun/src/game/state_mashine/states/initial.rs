@@ -1,12 +1,14 @@
 use crate::game::events::Event;
 use crate::game::flash::Flash;
+use crate::game::settings::Settings;
 use crate::game::state_mashine::states::start_menu::StartMenu;
 use crate::game::state_mashine::State;
+use crate::game::tile_cache::TileCache;
 use crate::ili9486::Display;
 use crate::ili9486::GameDisplay;
+use crate::logging::info;
 use alloc::boxed::Box;
 use async_trait::async_trait;
-use defmt::info;
 use embedded_graphics::pixelcolor::Rgb565;
 extern crate alloc;
 
@@ -24,10 +26,19 @@ where
     D: GameDisplay + Send + Display<u8, Color = Rgb565>,
     F: Flash + Send + Sync,
 {
-    async fn on_event(&mut self, _event: Event, _display: &mut D) -> Option<Box<dyn State<D, F>>> {
+    async fn on_event(
+        &mut self,
+        _event: Event,
+        display: &mut D,
+        flash: &mut F,
+        _tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
         info!("Init State");
+        let settings = Settings::load(flash).await;
+        info!("Settings {:?}", settings);
+        settings.apply(display).await;
         Some(Box::new(StartMenu::new()))
     }
 
-    async fn on_init(&mut self, _display: &mut D, _flash: &mut F) {}
+    async fn on_init(&mut self, _display: &mut D, _flash: &mut F, _tiles: &mut TileCache) {}
 }
@@ -1,117 +1,441 @@
+use self::actions::{Pos, Target};
+use self::builtin::{builtin_level, BUILTIN_LEVELS};
+use self::compose::{cells_area, touched_cells, Canvas};
 use self::items::spell::Spell;
-use super::spell::{SpellCommands, MAX_COMMANDS};
+use self::items::{exit::Exit, sprite::StaticSprite, wizard::Wizard, Item};
+use super::level_select::LevelSelect;
+use super::spell::{Spell as SpellScreen, SpellCommands, MAX_COMMANDS};
+use super::start_menu::StartMenu;
+use super::State;
 use crate::game::events::{Buttons, Event, States};
+use crate::game::flash::{Flash, LEVELS_OFFSET};
+use crate::game::save::{SaveSlots, MAX_LEVELS};
+use crate::game::settings::{self, Choice, Theme};
+use crate::game::text::Text;
+use crate::game::tile_cache::{TileCache, TileKey, TILE_BYTES};
 use crate::game::tiles::*;
 use crate::game::{MAX_X, MAX_Y};
 use crate::ili9486::Display;
 use crate::ili9486::GameDisplay;
+use crate::logging::{error, info, warn, Display2Format};
 use alloc::boxed::Box;
+use async_trait::async_trait;
 use core::fmt::Write;
-use core::marker::PhantomData;
+use core::mem::transmute;
 use embedded_graphics::pixelcolor::Rgb565;
-use embedded_graphics::prelude::Point;
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::{PointsIter, Rectangle};
 use grid::Grid;
-use hashbrown::HashMap;
+use hashbrown::HashSet;
 use heapless::{String, Vec};
+use koldun_level_format::{FormatError, ItemKind, LevelData, Placement, LEVEL_SIZE};
 
 extern crate alloc;
 
 pub mod actions;
+pub mod builtin;
+pub mod compose;
 pub mod grid;
 pub mod items;
-pub mod level1;
 
-pub enum Levels {
-    Level1,
-}
-
-pub struct Level<L> {
+pub struct Level {
+    index: usize,
     grid: Grid,
-    tiles: HashMap<usize, [u8; 32 * 32 * 2]>,
+    /// The level's own theme, the player's one if it has none
+    theme: Theme,
     block: bool,
-    idx: PhantomData<L>,
+    /// Dirty cells are put together here before they are drawn
+    canvas: Canvas,
+    /// Wizard moves made so far, the score kept as best moves
+    moves: u16,
+    loaded: bool,
+    error: Option<FormatError>,
 }
 
-impl<L> Level<L> {
-    pub async fn redraw_all<D>(&mut self, display: &mut D)
+impl Level {
+    pub fn new(index: usize) -> Self {
+        Level {
+            index,
+            grid: Grid::new(),
+            theme: settings::current().theme,
+            block: Default::default(),
+            canvas: Canvas::new(),
+            moves: 0,
+            loaded: false,
+            error: None,
+        }
+    }
+
+    pub fn from_data(index: usize, data: &LevelData) -> Result<Self, FormatError> {
+        let mut level = Level::new(index);
+        level.grid = Level::build_grid(data)?;
+        level.theme = level_theme(data.theme);
+        level.loaded = true;
+        Ok(level)
+    }
+
+    pub fn from_spell(
+        index: usize,
+        grid: &mut Grid,
+        moves: u16,
+        theme: Theme,
+        commands: Vec<SpellCommands, MAX_COMMANDS>,
+    ) -> Self {
+        let mut level = Level::new(index);
+        level.grid = Grid::new_from(grid);
+        level.moves = moves;
+        level.theme = theme;
+        level.loaded = true;
+
+        if commands.len() > 0 {
+            let spell: Box<Spell> =
+                Box::new(Spell::new(Point::new(0, 0), 0, TileId::Fence, commands));
+
+            level.grid.set_item(0, 0, spell);
+        };
+
+        level
+    }
+
+    fn build_grid(data: &LevelData) -> Result<Grid, FormatError> {
+        let mut ids = [[TileId::Empty; MAX_X]; MAX_Y];
+        for (y, row) in data.tiles.iter().enumerate() {
+            for (x, img_id) in row.iter().enumerate() {
+                ids[y][x] = TileId::try_from(u16::from(*img_id))
+                    .map_err(|_| FormatError::UnknownTile(*img_id))?;
+            }
+        }
+        let mut grid: Grid = ids.into();
+        Level::place_items(&mut grid, data.items());
+        Ok(grid)
+    }
+
+    fn place_items<'a>(grid: &mut Grid, placements: impl Iterator<Item = &'a Placement>) {
+        for placement in placements {
+            let (x, y) = (placement.x as usize, placement.y as usize);
+            let coords = Point::new(x as i32, y as i32);
+
+            match placement.kind {
+                ItemKind::Wizard => {
+                    let wizard: Item<Wizard> = Item::new(coords, 1, TileId::WizardIdle1);
+                    grid.set_item(x, y, Box::new(wizard));
+                }
+                ItemKind::Exit => {
+                    let exit: Item<Exit> = Item::new(coords, 0, TileId::ExitOpen);
+                    grid.set_item(x, y, Box::new(exit));
+                }
+                ItemKind::Spider => {
+                    let spider: Item<StaticSprite> = Item::new(coords, 1, TileId::Spider1);
+                    grid.set_item(x, y, Box::new(spider));
+                }
+                ItemKind::Door => {
+                    let door: Item<StaticSprite> = Item::new(coords, 1, TileId::DoorWood);
+                    grid.set_item(x, y, Box::new(door));
+                }
+            }
+        }
+    }
+
+    pub async fn redraw_all<D>(&mut self, display: &mut D, tiles: &mut TileCache)
     where
         D: GameDisplay + Display<u8, Color = Rgb565> + Send,
     {
         for x in 0..MAX_X {
             for y in 0..MAX_Y {
-                let img_id = self.grid.tile_id(x, y);
+                let cell = Rectangle::new(Point::new(x as i32, y as i32), Size::new(1, 1));
+                self.draw_cells(display, tiles, cell, None, None).await;
+            }
+        }
+    }
 
-                let data = self.tiles.get(&img_id).expect(format_err(img_id).as_str());
-                display
-                    .draw_tile(Point::new(32 * x as i32, 32 * y as i32), data)
+    /// Draws `cells` with all their layers but the item at `skip`, and `sprite` over
+    /// them at its place on the screen. A cell showing a single tile is drawn as it is,
+    /// anything else is put together on the canvas and sent at once
+    async fn draw_cells<D>(
+        &mut self,
+        display: &mut D,
+        tiles: &mut TileCache,
+        cells: Rectangle,
+        skip: Option<Target>,
+        sprite: Option<(TileId, Point)>,
+    ) where
+        D: GameDisplay + Display<u8, Color = Rgb565> + Send,
+    {
+        let palette = self.theme.palette();
+        let area = cells_area(cells);
+        if cells.size == Size::new(1, 1) && sprite.is_none() {
+            let (x, y) = (cells.top_left.x as usize, cells.top_left.y as usize);
+            if let [img_id] = self.grid.layers(x, y, None).as_slice() {
+                tiles
+                    .draw(display, area.top_left, TileKey::themed(*img_id, palette))
                     .await;
+                return;
             }
         }
+
+        self.canvas.start(area);
+        for cell in cells.points() {
+            let (x, y) = (cell.x as usize, cell.y as usize);
+            let skip = skip
+                .filter(|target| (target.x, target.y) == (x, y))
+                .map(|target| target.z);
+            let origin = Point::new((TILE_SIZE_X * x) as i32, (TILE_SIZE_Y * y) as i32);
+            for (z, img_id) in self.grid.layers(x, y, skip).iter().enumerate() {
+                // Nothing shows through the bottom layer
+                let mask = match z {
+                    0 => [0xff; MASK_BYTES],
+                    _ => img_id.mask(),
+                };
+                let key = TileKey::themed(*img_id, palette);
+                self.canvas.draw_tile(tiles, key, &mask, origin);
+            }
+        }
+
+        if let Some((img_id, origin)) = sprite {
+            let key = TileKey::themed(img_id, palette);
+            self.canvas.draw_tile(tiles, key, &img_id.mask(), origin);
+        }
+        display.draw_data(area, self.canvas.data()).await;
     }
 
-    pub async fn _on_event<D>(&mut self, event: Event, display: &mut D) -> (bool, bool)
+    pub async fn _on_event<D>(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        tiles: &mut TileCache,
+    ) -> (bool, bool, bool)
     where
         D: GameDisplay + Display<u8, Color = Rgb565> + Send,
     {
         match event {
-            Event::Button(Buttons::Reset(States::Pressed)) => return (false, true),
+            Event::Button(Buttons::Reset(States::Pressed)) => return (false, false, true),
             _ => (),
         }
 
         if self.block {
             match event {
-                Event::Button(_) => return (false, false),
+                Event::Button(_) => return (false, false, false),
                 _ => (),
             }
         }
 
         let requests = self.grid.on_event(&event);
-        let (reactions, to_redraw, block, is_win) = self.grid.on_actions(requests);
+        let (reactions, to_redraw, block, is_win, is_lost) = self.grid.on_actions(requests);
         if let Some(block) = block {
             self.block = block
         };
+        // Only a move that got through blocks the controls
+        if let (Event::Button(_), Some(true)) = (&event, block) {
+            self.moves = self.moves.saturating_add(1);
+        }
 
         self.grid.on_reactions(reactions);
 
-        // Redraw cells
-        for request in to_redraw {
-            let img_id = self.grid.tile_id(request.target.x, request.target.y);
-            let data = self.tiles.get(&img_id).expect("Unknown img_id");
-            display
-                .draw_tile(
-                    Point::new(
-                        (TILE_SIZE_X as isize * request.target.x as isize + request.shift.x) as i32,
-                        (TILE_SIZE_Y as isize * request.target.y as isize + request.shift.y) as i32,
-                    ),
-                    data,
-                )
+        // Redraw cells, a moving sprite along with all the cells it touches
+        let moving: Vec<(Target, Point, Rectangle), 32> = to_redraw
+            .iter()
+            .filter(|request| request.shift != Pos::default())
+            .map(|request| {
+                let origin = Point::new(
+                    (TILE_SIZE_X as isize * request.target.x as isize + request.shift.x) as i32,
+                    (TILE_SIZE_Y as isize * request.target.y as isize + request.shift.y) as i32,
+                );
+                (request.target, origin, touched_cells(origin))
+            })
+            .collect();
+        for request in to_redraw.iter() {
+            let cell = Point::new(request.target.x as i32, request.target.y as i32);
+            if !moving.iter().any(|(_, _, cells)| cells.contains(cell)) {
+                let cell = Rectangle::new(cell, Size::new(1, 1));
+                self.draw_cells(display, tiles, cell, None, None).await;
+            }
+        }
+        for (target, origin, cells) in moving {
+            let sprite = self
+                .grid
+                .item_tile_id(target)
+                .map(|img_id| (img_id, origin));
+            self.draw_cells(display, tiles, cells, Some(target), sprite)
                 .await;
         }
-        (is_win, false)
+        (is_win, is_lost, false)
     }
 
-    pub fn from_spell(grid: &mut Grid, commands: Vec<SpellCommands, MAX_COMMANDS>) -> Self {
-        let mut grid = Grid::new_from(grid);
-        let tiles: HashMap<usize, [u8; 32 * 32 * 2]> = HashMap::with_capacity(16);
+    pub fn moves(&self) -> u16 {
+        self.moves
+    }
 
-        if commands.len() > 0 {
-            let spell: Box<Spell> =
-                Box::new(Spell::new(Point::new(0, 0), 0, Tile::fence_id(), commands));
+    /// Records the win in the save and picks the state to go on with: the next
+    /// level, or the level select once the campaign runs out of levels
+    async fn on_win<D, F>(&mut self, flash: &mut F) -> Box<dyn State<D, F>>
+    where
+        D: GameDisplay + Display<u8, Color = Rgb565> + Send,
+        F: Flash + Send + Sync,
+    {
+        info!("Level {} won in {} moves", self.index, self.moves);
 
-            grid.set_item(0, 0, spell);
-        };
+        let mut slots = SaveSlots::load(flash).await;
+        let mut data = slots.data().cloned().unwrap_or_default();
+        data.progress.complete(self.index, self.moves);
+        if let Err(err) = slots.store(flash, &data).await {
+            error!("Progress not saved: {}", Display2Format(&err));
+        }
 
-        Self {
-            grid,
-            tiles,
-            block: Default::default(),
-            idx: Default::default(),
+        let next = self.index + 1;
+        match next < MAX_LEVELS && level_exists(flash, next).await {
+            true => Box::new(Level::new(next)),
+            false => Box::new(LevelSelect::new(self.index)),
+        }
+    }
+
+    /// Renders the tiles the grid can show ahead of play, with the animation
+    /// frames of each one, as many as the cache budget holds. Anything left out
+    /// is rendered when it is first drawn
+    fn load_tiles(&self, tiles: &mut TileCache) {
+        let mut needed: HashSet<TileId> = HashSet::new();
+        for img_id in self.grid.tile_ids() {
+            let mut next = Some(img_id);
+            while let Some(img_id) = next.filter(|img_id| needed.insert(*img_id)) {
+                next = img_id.meta().animated_with;
+            }
+        }
+
+        let palette = self.theme.palette();
+        for img_id in needed.into_iter().take(tiles.budget() / TILE_BYTES) {
+            tiles.themed(img_id, palette);
+        }
+    }
+}
+
+#[async_trait]
+impl<D, F> State<D, F> for Level
+where
+    D: GameDisplay + Display<u8, Color = Rgb565> + Send,
+    F: Flash + Send + Sync,
+{
+    async fn on_event(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        flash: &mut F,
+        tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
+        if self.error.is_some() {
+            return match event {
+                Event::Button(_) => Some(Box::new(StartMenu::new())),
+                _ => None,
+            };
+        }
+
+        let (is_win, is_lost, is_spell) = self._on_event(event, display, tiles).await;
+
+        match is_win {
+            true => {
+                return Some(self.on_win(flash).await);
+            }
+            false => (),
+        }
+
+        // Lost, start the level over
+        if is_lost {
+            info!("Level {} lost", self.index);
+            return Some(Box::new(Level::new(self.index)));
         }
+
+        match is_spell {
+            true => Some(Box::new(SpellScreen::from_grid(
+                &mut self.grid,
+                self.index,
+                self.moves,
+                self.theme,
+            ))),
+            false => None,
+        }
+    }
+
+    async fn on_init(&mut self, display: &mut D, flash: &mut F, tiles: &mut TileCache) {
+        info!("Level {} Init", self.index);
+
+        if !self.loaded {
+            match load_grid(flash, self.index).await {
+                Ok((grid, theme)) => {
+                    self.grid = grid;
+                    self.theme = theme;
+                    self.loaded = true;
+                }
+                Err(err) => {
+                    error!("Level {}: {}", self.index, Display2Format(&err));
+                    self.error = Some(err);
+
+                    let settings = settings::current();
+                    let palette = settings.theme.palette();
+                    display.clear(palette.wall_bg).unwrap();
+                    let mut message: String<48> = String::new();
+                    write!(
+                        &mut message,
+                        "{} {} {}",
+                        Text::Level.get(settings.language),
+                        self.index + 1,
+                        Text::IsBroken.get(settings.language)
+                    )
+                    .unwrap();
+                    display.draw_text(
+                        message.as_str(),
+                        Point::new(170, 150),
+                        palette.wizard_fg,
+                        None,
+                    );
+                    return;
+                }
+            }
+        }
+
+        self.load_tiles(tiles);
+        self.redraw_all(display, tiles).await
+    }
+
+    fn level(&self) -> Option<usize> {
+        Some(self.index)
     }
 }
 
-fn format_err(img_id: usize) -> String<24> {
-    let mut s: String<24> = String::new();
-    write!(&mut s, "Unknown img_id: {}", img_id).unwrap();
-    s
+pub async fn load_level<F: Flash>(flash: &mut F, index: usize) -> Result<LevelData, FormatError> {
+    let data = &mut [0u32; LEVEL_SIZE / 4];
+    flash.load(LEVELS_OFFSET + index * LEVEL_SIZE, data).await;
+    let data = unsafe { transmute::<&[u32; LEVEL_SIZE / 4], &[u8; LEVEL_SIZE]>(data) };
+    LevelData::decode(data)
+}
+
+/// Grid and theme of level `index`, from flash or built in when flash has none
+async fn load_grid<F: Flash>(flash: &mut F, index: usize) -> Result<(Grid, Theme), FormatError> {
+    match load_level(flash, index).await {
+        Ok(data) => Level::build_grid(&data).map(|grid| (grid, level_theme(data.theme))),
+        Err(err) => {
+            warn!("Level {} not in flash: {}", index, Display2Format(&err));
+            builtin_level(index)
+                .map(|mut level| {
+                    Level::place_items(&mut level.grid, level.items.iter());
+                    (level.grid, level_theme(level.theme))
+                })
+                .ok_or(err)
+        }
+    }
+}
+
+/// `Grid::content_hash` of level `index` as it is loaded, `None` if it can't be
+pub async fn level_hash<F: Flash>(flash: &mut F, index: usize) -> Option<u32> {
+    let (grid, _) = load_grid(flash, index).await.ok()?;
+    Some(grid.content_hash())
+}
+
+/// `true` if `index` can be played, from flash or built in
+pub async fn level_exists<F: Flash>(flash: &mut F, index: usize) -> bool {
+    load_level(flash, index).await.is_ok() || index < BUILTIN_LEVELS
+}
+
+/// Theme set by the level, the player's one when it has none
+fn level_theme(theme: Option<u8>) -> Theme {
+    theme
+        .and_then(|theme| Theme::from_index(theme as usize))
+        .unwrap_or(settings::current().theme)
 }
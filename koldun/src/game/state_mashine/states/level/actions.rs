@@ -1,6 +1,5 @@
-use defmt::Format;
-
-#[derive(Debug, Format)]
+#[derive(Debug)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub struct Action {
     pub target: Target,
     pub action: Actions,
@@ -12,7 +11,8 @@ impl Action {
     }
 }
 
-#[derive(Debug, Format)]
+#[derive(Debug)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum Actions {
     Move { dest: MoveDestination, who: Who },
     RedrawAnim(i8, i8, Target),
@@ -22,7 +22,8 @@ pub enum Actions {
     Win,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy, Format)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub struct Target {
     pub x: usize,
     pub y: usize,
@@ -35,7 +36,8 @@ impl Target {
     }
 }
 
-#[derive(Debug, Clone, Copy, Format)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum MoveDestination {
     Up,
     Down,
@@ -43,7 +45,8 @@ pub enum MoveDestination {
     Right,
 }
 
-#[derive(Debug, Clone, Copy, Format)]
+#[derive(Debug, Clone, Copy)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum Who {
     Wizard,
 }
@@ -0,0 +1,26 @@
+use super::grid::Grid;
+use crate::game::tiles::TileId;
+use koldun_level_format::Placement;
+use koldun_macro_derive::level;
+
+/// Number of levels compiled into the firmware
+pub const BUILTIN_LEVELS: usize = 1;
+
+/// A level compiled into the firmware by `level!`
+pub struct BuiltinLevel {
+    pub name: &'static str,
+    /// Index in `THEMES`, `None` for the player's theme
+    pub theme: Option<u8>,
+    /// Tiles of the level, items are not placed on it
+    pub grid: Grid,
+    pub items: &'static [Placement],
+}
+
+/// Levels compiled into the firmware, used when flash has no valid level at `index`.
+/// They are built from the same sources as the level image
+pub fn builtin_level(index: usize) -> Option<BuiltinLevel> {
+    match index {
+        0 => Some(level!("resources/levels/src/01_ruins.lvl")),
+        _ => None,
+    }
+}
@@ -0,0 +1,158 @@
+//! Dirty parts of the screen put together offscreen before they are sent to the display.
+//!
+//! A transparent tile covers only the pixels of its mask, so a sprite keeps the floor
+//! under it. A sprite moving between cells is off the grid by any number of pixels and
+//! touches up to four cells, they are drawn in a single `Canvas` and sent at once so
+//! the display never shows the floor without the sprite on it. With the tile cache off
+//! tiles are expanded from their packed bits straight into the canvas.
+
+use crate::game::tile_cache::{TileCache, TileKey, TILE_BYTES};
+use crate::game::tiles::{MASK_BYTES, TILE_SIZE_X, TILE_SIZE_Y};
+use crate::game::{MAX_X, MAX_Y};
+use alloc::boxed::Box;
+use alloc::vec;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::{Point, RawData, Size};
+use embedded_graphics::primitives::Rectangle;
+extern crate alloc;
+
+/// Cells across and down the canvas holds, enough for a sprite between four cells
+pub const CANVAS_CELLS: usize = 2;
+
+pub const CANVAS_WIDTH: usize = CANVAS_CELLS * TILE_SIZE_X;
+pub const CANVAS_HEIGHT: usize = CANVAS_CELLS * TILE_SIZE_Y;
+
+/// Pixels of a part of the screen, big endian RGB565 row by row as `draw_data` takes them
+pub struct Canvas {
+    /// Part of the screen drawn, no larger than `CANVAS_WIDTH` by `CANVAS_HEIGHT`
+    area: Rectangle,
+    /// On the heap, it is too large for the stack
+    data: Box<[u8]>,
+}
+
+impl Canvas {
+    pub fn new() -> Self {
+        Canvas {
+            area: Rectangle::zero(),
+            data: vec![0; CANVAS_WIDTH * CANVAS_HEIGHT * 2].into_boxed_slice(),
+        }
+    }
+
+    /// Starts drawing `area` of the screen, cut to the size of the canvas
+    pub fn start(&mut self, area: Rectangle) {
+        let size = Size::new(
+            area.size.width.min(CANVAS_WIDTH as u32),
+            area.size.height.min(CANVAS_HEIGHT as u32),
+        );
+        self.area = Rectangle::new(area.top_left, size);
+    }
+
+    pub fn area(&self) -> Rectangle {
+        self.area
+    }
+
+    /// Draws a rendered tile with its top left corner at `origin` on the screen, where
+    /// its `mask` is set. Whatever falls outside the area drawn is cut
+    pub fn overlay(&mut self, pixels: &[u8; TILE_BYTES], mask: &[u8; MASK_BYTES], origin: Point) {
+        self.blit(mask, origin, |from| {
+            [pixels[from * 2], pixels[from * 2 + 1]]
+        });
+    }
+
+    /// Like `overlay`, with the pixels expanded from the tile's packed `bits` of `depth`
+    /// bits each as they are drawn, nothing is rendered beforehand
+    pub fn overlay_bits(
+        &mut self,
+        bits: &[u8],
+        depth: usize,
+        colors: &[Rgb565],
+        mask: &[u8; MASK_BYTES],
+        origin: Point,
+    ) {
+        self.blit(mask, origin, |from| {
+            let value = (bits[from * depth / 8] >> (from * depth % 8)) & ((1 << depth) - 1);
+            RawU16::from(colors[value as usize])
+                .into_inner()
+                .to_be_bytes()
+        });
+    }
+
+    /// Draws `key` at `origin` where `mask` is set, rendered by the cache or straight
+    /// from the tile's bits when the cache is off
+    pub fn draw_tile(
+        &mut self,
+        tiles: &mut TileCache,
+        key: TileKey,
+        mask: &[u8; MASK_BYTES],
+        origin: Point,
+    ) {
+        match tiles.is_enabled() {
+            true => self.overlay(tiles.get(key), mask, origin),
+            false => {
+                let (bits, depth) = (key.id.bits(), key.id.depth());
+                self.overlay_bits(bits, depth, key.colors(), mask, origin)
+            }
+        }
+    }
+
+    /// Writes the pixel `pixel` gives for each index of the tile where `mask` is set
+    fn blit(&mut self, mask: &[u8; MASK_BYTES], origin: Point, pixel: impl Fn(usize) -> [u8; 2]) {
+        let (width, height) = (self.area.size.width as i32, self.area.size.height as i32);
+        let origin = origin - self.area.top_left;
+        for y in 0..TILE_SIZE_Y as i32 {
+            let to_y = y + origin.y;
+            if !(0..height).contains(&to_y) {
+                continue;
+            }
+
+            for x in 0..TILE_SIZE_X as i32 {
+                let to_x = x + origin.x;
+                let from = y as usize * TILE_SIZE_X + x as usize;
+                if !(0..width).contains(&to_x) || (mask[from / 8] >> (from % 8)) & 1 == 0 {
+                    continue;
+                }
+
+                let to = (to_y * width + to_x) as usize;
+                self.data[to * 2..to * 2 + 2].copy_from_slice(&pixel(from));
+            }
+        }
+    }
+
+    /// Pixels of the area drawn
+    pub fn data(&self) -> &[u8] {
+        let size = self.area.size;
+        &self.data[..(size.width * size.height * 2) as usize]
+    }
+}
+
+impl Default for Canvas {
+    fn default() -> Self {
+        Canvas::new()
+    }
+}
+
+/// Cells a tile with its top left corner at `origin` on the screen touches, as a
+/// rectangle of cells cut to the grid
+pub fn touched_cells(origin: Point) -> Rectangle {
+    let (width, height) = (TILE_SIZE_X as i32, TILE_SIZE_Y as i32);
+    let first = Point::new(origin.x.div_euclid(width), origin.y.div_euclid(height));
+    let last = Point::new(
+        (origin.x + width - 1).div_euclid(width),
+        (origin.y + height - 1).div_euclid(height),
+    );
+    let grid = Rectangle::new(Point::zero(), Size::new(MAX_X as u32, MAX_Y as u32));
+    grid.intersection(&Rectangle::with_corners(first, last))
+}
+
+/// Part of the screen the `cells` take
+pub fn cells_area(cells: Rectangle) -> Rectangle {
+    let (width, height) = (TILE_SIZE_X as i32, TILE_SIZE_Y as i32);
+    Rectangle::new(
+        Point::new(cells.top_left.x * width, cells.top_left.y * height),
+        Size::new(
+            cells.size.width * TILE_SIZE_X as u32,
+            cells.size.height * TILE_SIZE_Y as u32,
+        ),
+    )
+}
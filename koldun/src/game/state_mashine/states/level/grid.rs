@@ -1,12 +1,14 @@
-use super::actions::{Action, Actions, MoveDestination, Pos, RedrawRequest, Target};
+use super::actions::{Action, Actions, MoveDestination, Pos, RedrawRequest, Target, Who};
 use super::items::Kinds;
 use super::items::{sprite::StaticSprite, Drawable, Item, ItemTrait, MAX_ACTIONS_PER_EVENT};
 use crate::game::events::Event;
+use crate::game::tiles::{TileId, TileMeta};
 use crate::game::{MAX_X, MAX_Y};
 use crate::{add_to_redraw, h_vec};
 use alloc::boxed::Box;
 use core::error::Error;
 use core::fmt;
+use core::iter::once;
 use core::ops::{Index, IndexMut};
 use embedded_graphics::prelude::Point;
 use heapless::Vec;
@@ -16,9 +18,14 @@ extern crate alloc;
 const LAYERS: usize = 2;
 pub const MAX_EVENTS: usize = 128;
 
+/// Tiles a cell can be drawn with, its terrain and an item on each layer
+pub type Layers = Vec<TileId, { LAYERS + 1 }>;
+
 pub struct Cell {
     // coords: Point,
     items: Vec<Option<Box<dyn ItemTrait>>, LAYERS>,
+    /// Tile the level put here, items on top don't change it
+    terrain: TileId,
 }
 
 impl Cell {
@@ -29,10 +36,13 @@ impl Cell {
                 items.push_unchecked(None);
             }
         }
-        Cell { items }
+        Cell {
+            items,
+            terrain: TileId::Empty,
+        }
     }
 
-    fn new_static_sprite(coords: Point, img_id: usize, z_order: usize) -> Self {
+    fn new_static_sprite(coords: Point, img_id: TileId, z_order: usize) -> Self {
         let mut items: Vec<Option<Box<dyn ItemTrait>>, LAYERS> = Vec::new();
         let sprite: Item<StaticSprite> = Item::new(coords, z_order, img_id);
 
@@ -52,7 +62,57 @@ impl Cell {
                 items.push_unchecked(None);
             }
         }
-        Cell { items }
+        Cell {
+            items,
+            terrain: img_id,
+        }
+    }
+
+    pub fn meta(&self) -> TileMeta {
+        self.terrain.meta()
+    }
+
+    /// Terrain and items of the cell with the frames the items switch to
+    fn tile_ids(&self) -> impl Iterator<Item = TileId> + '_ {
+        let items = self.items.iter().flatten();
+        let frames =
+            items.flat_map(|item| once(item.tile_id()).chain(item.frames().iter().copied()));
+        once(self.terrain).chain(frames)
+    }
+
+    /// Tiles drawn in the cell from the bottom up, leaving out the item on layer `skip`.
+    /// The terrain goes under the items unless its own sprite is one of them, and
+    /// whatever an opaque tile hides is left out
+    fn layers(&self, skip: Option<usize>) -> Layers {
+        let terrain_sprite = self
+            .items
+            .iter()
+            .flatten()
+            .any(|item| item.kind() == Kinds::Sprite && item.tile_id() == self.terrain);
+
+        let mut layers = Layers::new();
+        if !terrain_sprite {
+            layers.push(self.terrain).unwrap();
+        }
+        for (z, item) in self.items.iter().enumerate() {
+            match item {
+                Some(item) if Some(z) != skip => layers.push(item.tile_id()).unwrap(),
+                _ => (),
+            }
+        }
+
+        match layers.iter().rposition(|tile| !tile.meta().transparent) {
+            Some(top) => Layers::from_slice(&layers[top..]).unwrap(),
+            None => layers,
+        }
+    }
+
+    /// `true` if `who` may step into the cell, as far as the terrain goes
+    fn can_enter(&self, who: Who) -> bool {
+        let meta = self.meta();
+        match who {
+            Who::Wizard => meta.walkable,
+        }
     }
 
     pub fn set_item(&mut self, item: Box<dyn ItemTrait>) {
@@ -112,7 +172,7 @@ impl Default for Cell {
 }
 
 impl Drawable for Cell {
-    fn tile_id(&self) -> usize {
+    fn tile_id(&self) -> TileId {
         if let Some(item) = self.items.iter().rev().find_map(|item| item.as_deref()) {
             return item.tile_id();
         }
@@ -149,11 +209,13 @@ impl Grid {
         Vec<RedrawRequest, 32>,
         Option<bool>,
         bool,
+        bool,
     ) {
         let mut reactions: Vec<Action, MAX_EVENTS> = Vec::new();
         let mut to_redraw: Vec<RedrawRequest, 32> = Vec::new();
         let mut block: Option<bool> = None;
         let mut is_win: bool = false;
+        let mut is_lost: bool = false;
 
         for action in actions {
             match action {
@@ -162,7 +224,7 @@ impl Grid {
                     target,
                     action: Actions::Move { dest, who },
                 } => {
-                    if let Ok(mut new_target) = self.move_item(target, dest) {
+                    if let Ok(mut new_target) = self.move_item(target, dest, who) {
                         // Successfull move, add reaction
                         for z in 0..LAYERS {
                             new_target.z = z;
@@ -173,6 +235,12 @@ impl Grid {
 
                         // Block controlls
                         block = Some(true);
+
+                        // The wizard walked into a hazard, the level is lost
+                        let cell = &self.0[new_target.y][new_target.x];
+                        if matches!(who, Who::Wizard) && cell.meta().hazard {
+                            is_lost = true;
+                        }
                     }
                 }
 
@@ -217,7 +285,7 @@ impl Grid {
                         let mut spell = init_cell.take_item(0).unwrap();
 
                         if let Some(cell) = self.get_cell_mut(target.x, target.y) {
-                            if !cell.has_item(target.z) {
+                            if !cell.has_item(target.z) && !cell.meta().blocks_spells {
                                 spell.set_x(target.x);
                                 spell.set_y(target.y);
                                 spell.set_z(target.z);
@@ -242,7 +310,7 @@ impl Grid {
                 } => is_win = true,
             }
         }
-        (reactions, to_redraw, block, is_win)
+        (reactions, to_redraw, block, is_win, is_lost)
     }
 
     pub fn on_reactions(&mut self, reactions: Vec<Action, MAX_EVENTS>) {
@@ -251,17 +319,56 @@ impl Grid {
         });
     }
 
-    pub fn tile_id(&self, x: usize, y: usize) -> usize {
+    pub fn tile_id(&self, x: usize, y: usize) -> TileId {
         self.0[y][x].tile_id()
     }
 
+    /// Tiles drawn in cell `x`, `y` from the bottom up, see `Cell::layers`
+    pub fn layers(&self, x: usize, y: usize, skip: Option<usize>) -> Layers {
+        self.0[y][x].layers(skip)
+    }
+
+    /// Tile of the item at `target`, if there is one
+    pub fn item_tile_id(&self, target: Target) -> Option<TileId> {
+        let cell = self.get_cell_ref(target.x, target.y)?;
+        cell.items[target.z].as_deref().map(|item| item.tile_id())
+    }
+
+    /// Every tile the level can show, repeats included
+    pub fn tile_ids(&self) -> impl Iterator<Item = TileId> + '_ {
+        self.0.iter().flatten().flat_map(Cell::tile_ids)
+    }
+
+    /// FNV-1a hash of the terrain and the items of every cell, grids that play the
+    /// same hash the same whether they came from flash or were built in
+    pub fn content_hash(&self) -> u32 {
+        let mut hash: u32 = 0x811c_9dc5;
+        for cell in self.0.iter().flatten() {
+            let items = cell
+                .items
+                .iter()
+                .map(|item| item.as_ref().map_or(u16::MAX, |item| item.tile_id() as u16));
+            for id in once(cell.terrain as u16).chain(items) {
+                for byte in id.to_le_bytes() {
+                    hash = (hash ^ byte as u32).wrapping_mul(0x0100_0193);
+                }
+            }
+        }
+        hash
+    }
+
     pub fn set_item(&mut self, x: usize, y: usize, item: Box<dyn ItemTrait>) {
         if let Some(cell) = self.get_cell_mut(x, y) {
             cell.set_item(item);
         }
     }
 
-    fn move_item(&mut self, src: Target, dest: MoveDestination) -> Result<Target, CellError> {
+    fn move_item(
+        &mut self,
+        src: Target,
+        dest: MoveDestination,
+        who: Who,
+    ) -> Result<Target, CellError> {
         if let Some(cell) = self.get_cell_mut(src.x, src.y) {
             let item = cell.take_item(src.z);
             if let Some(item) = item {
@@ -291,7 +398,7 @@ impl Grid {
                 };
 
                 if let Some(cell) = dest_cell {
-                    if !cell.has_item(item.z_level()) {
+                    if !cell.has_item(item.z_level()) && cell.can_enter(who) {
                         cell.set_item(item);
                         new_target.z = src.z;
                         return Ok(new_target);
@@ -352,6 +459,7 @@ impl Grid {
             for y in 0..MAX_Y {
                 let src_cell = other.get_cell_mut(x, y).unwrap();
                 let dst_cell = grid.get_cell_mut(x, y).unwrap();
+                dst_cell.terrain = src_cell.terrain;
 
                 for z_level in 0..LAYERS {
                     if let Some(item) = src_cell.take_item(z_level) {
@@ -364,13 +472,13 @@ impl Grid {
     }
 }
 
-impl From<[[usize; MAX_X]; MAX_Y]> for Grid {
-    fn from(array: [[usize; MAX_X]; MAX_Y]) -> Self {
+impl From<[[TileId; MAX_X]; MAX_Y]> for Grid {
+    fn from(array: [[TileId; MAX_X]; MAX_Y]) -> Self {
         let mut grid: [[Cell; MAX_X]; MAX_Y] = Default::default();
         for x in 0..MAX_X {
             for y in 0..MAX_Y {
                 let img_id = array[y][x];
-                let z_order = if img_id <= 32 { 0 } else { 1 };
+                let z_order = img_id.meta().layer;
                 grid[y][x] =
                     Cell::new_static_sprite(Point::new(x as i32, y as i32), img_id, z_order);
             }
@@ -1,5 +1,6 @@
 use super::actions::{Action, Actions, Target, Who};
 use super::Event;
+use crate::game::tiles::TileId;
 use crate::game::{MAX_X, MAX_Y};
 use crate::h_vec;
 use crate::ili9486::GameDisplay;
@@ -38,7 +39,7 @@ pub trait Draw<D: GameDisplay> {
 }
 
 pub trait Drawable {
-    fn tile_id(&self) -> usize;
+    fn tile_id(&self) -> TileId;
 }
 
 pub trait ZLevel {
@@ -90,12 +91,17 @@ pub trait Coord<const MAX_X: usize, const MAX_Y: usize> {
 pub trait ItemTrait:
     OnEvent + OnReaction + Coord<MAX_X, MAX_Y> + Drawable + ZLevel + Send + Kind
 {
+    /// Tiles the item switches to on its own, besides the `animated_with` pairs of
+    /// the tile metadata. The level renders them up front
+    fn frames(&self) -> &'static [TileId] {
+        &[]
+    }
 }
 
 pub struct Item<I> {
     z_order: usize,
     coords: Point,
-    img_id: usize,
+    img_id: TileId,
     state: u8,
     start_animation: u128,
     time: u128,
@@ -128,13 +134,13 @@ impl<I> ZLevel for Item<I> {
 }
 
 impl<I> Drawable for Item<I> {
-    fn tile_id(&self) -> usize {
+    fn tile_id(&self) -> TileId {
         self.img_id
     }
 }
 
 impl<I> Item<I> {
-    pub fn new(coords: Point, z_order: usize, img_id: usize) -> Self {
+    pub fn new(coords: Point, z_order: usize, img_id: TileId) -> Self {
         Item {
             z_order,
             coords,
@@ -9,6 +9,7 @@ use crate::{
             level::actions::{MoveDestination, Target},
             spell::{SpellCommands, MAX_COMMANDS},
         },
+        tiles::TileId,
         MAX_X, MAX_Y,
     },
     h_vec,
@@ -21,7 +22,7 @@ const UNINITIALIZED: u8 = 0;
 pub struct Spell {
     z_order: usize,
     coords: Point,
-    img_id: usize,
+    img_id: TileId,
     state: u8,
     start_animation: u128,
     time: u128,
@@ -55,7 +56,7 @@ impl ZLevel for Spell {
 }
 
 impl Drawable for Spell {
-    fn tile_id(&self) -> usize {
+    fn tile_id(&self) -> TileId {
         self.img_id
     }
 }
@@ -70,7 +71,7 @@ impl Spell {
     pub fn new(
         coords: Point,
         z_order: usize,
-        img_id: usize,
+        img_id: TileId,
         commands: Vec<SpellCommands, MAX_COMMANDS>,
     ) -> Self {
         Spell {
@@ -4,9 +4,9 @@ use super::{
 };
 use crate::game::events::{Buttons, Event, States};
 use crate::game::state_mashine::states::level::actions::{MoveDestination, Who};
-use crate::game::tiles::{Tile, TILE_SIZE_X, TILE_SIZE_Y};
+use crate::game::tiles::{TileId, TILE_SIZE_X, TILE_SIZE_Y};
 use crate::h_vec;
-use defmt::warn;
+use crate::logging::warn;
 use heapless::Vec;
 extern crate alloc;
 
@@ -23,9 +23,27 @@ const MOVE_RIGHT2: u8 = 9;
 
 const MOVE_ANIM_TIME: u128 = 10;
 
+/// Every tile the wizard is drawn with, idle or walking
+const FRAMES: [TileId; 10] = [
+    TileId::WizardIdle1,
+    TileId::WizardIdle2,
+    TileId::WizardUp1,
+    TileId::WizardUp2,
+    TileId::WizardDown1,
+    TileId::WizardDown2,
+    TileId::WizardLeft1,
+    TileId::WizardLeft2,
+    TileId::WizardRight1,
+    TileId::WizardRight2,
+];
+
 pub struct Wizard;
 
-impl ItemTrait for Item<Wizard> {}
+impl ItemTrait for Item<Wizard> {
+    fn frames(&self) -> &'static [TileId] {
+        &FRAMES
+    }
+}
 
 impl OnEvent for Item<Wizard> {
     fn on_event(&mut self, event: &Event) -> Vec<Action, MAX_ACTIONS_PER_EVENT> {
@@ -40,8 +58,8 @@ impl OnEvent for Item<Wizard> {
                         if time % 5 == 0 {
                             self.swith_state(IDLE1, IDLE2);
                             match self.state {
-                                IDLE1 => self.img_id = Tile::wizard_idle2_id(),
-                                IDLE2 => self.img_id = Tile::wizard_idle1_id(),
+                                IDLE1 => self.img_id = TileId::WizardIdle2,
+                                IDLE2 => self.img_id = TileId::WizardIdle1,
                                 _ => {}
                             }
 
@@ -72,8 +90,8 @@ impl OnEvent for Item<Wizard> {
                         if time % 2 == 0 {
                             self.swith_state(MOVE_LEFT1, MOVE_LEFT2);
                             match self.state {
-                                MOVE_LEFT2 => self.img_id = Tile::wizard_left1_id(),
-                                MOVE_LEFT1 => self.img_id = Tile::wizard_left2_id(),
+                                MOVE_LEFT2 => self.img_id = TileId::WizardLeft1,
+                                MOVE_LEFT1 => self.img_id = TileId::WizardLeft2,
                                 _ => {}
                             }
                             let shift_x = TILE_SIZE_X as f32
@@ -109,8 +127,8 @@ impl OnEvent for Item<Wizard> {
                         if time % 2 == 0 {
                             self.swith_state(MOVE_RIGHT1, MOVE_RIGHT2);
                             match self.state {
-                                MOVE_RIGHT2 => self.img_id = Tile::wizard_right1_id(),
-                                MOVE_RIGHT1 => self.img_id = Tile::wizard_right2_id(),
+                                MOVE_RIGHT2 => self.img_id = TileId::WizardRight1,
+                                MOVE_RIGHT1 => self.img_id = TileId::WizardRight2,
                                 _ => {}
                             }
                             let shift_x = -(TILE_SIZE_X as f32)
@@ -146,8 +164,8 @@ impl OnEvent for Item<Wizard> {
                         if time % 2 == 0 {
                             self.swith_state(MOVE_UP1, MOVE_UP2);
                             match self.state {
-                                MOVE_UP2 => self.img_id = Tile::wizard_up1_id(),
-                                MOVE_UP1 => self.img_id = Tile::wizard_up2_id(),
+                                MOVE_UP2 => self.img_id = TileId::WizardUp1,
+                                MOVE_UP1 => self.img_id = TileId::WizardUp2,
                                 _ => {}
                             }
                             let shift_y = TILE_SIZE_Y as f32
@@ -183,8 +201,8 @@ impl OnEvent for Item<Wizard> {
                         if time % 2 == 0 {
                             self.swith_state(MOVE_DOWN1, MOVE_DOWN2);
                             match self.state {
-                                MOVE_DOWN2 => self.img_id = Tile::wizard_down1_id(),
-                                MOVE_DOWN1 => self.img_id = Tile::wizard_down2_id(),
+                                MOVE_DOWN2 => self.img_id = TileId::WizardDown1,
+                                MOVE_DOWN1 => self.img_id = TileId::WizardDown2,
                                 _ => {}
                             }
                             let shift_y = -(TILE_SIZE_Y as f32)
@@ -0,0 +1,198 @@
+use crate::game::events::{Buttons, Event, States};
+use crate::game::flash::Flash;
+use crate::game::save::{Progress, SaveSlots, MAX_LEVELS};
+use crate::game::settings;
+use crate::game::state_mashine::states::level::{level_exists, Level};
+use crate::game::state_mashine::states::start_menu::StartMenu;
+use crate::game::state_mashine::states::State;
+use crate::game::text::Text;
+use crate::game::tile_cache::TileCache;
+use crate::ili9486::Display;
+use crate::ili9486::GameDisplay;
+use crate::logging::info;
+use alloc::boxed::Box;
+use async_trait::async_trait;
+use core::fmt::Write;
+use core::marker::Send;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use heapless::String;
+extern crate alloc;
+
+/// Rows fitting under the title, the list scrolls to keep the selection in view
+const VISIBLE_ROWS: usize = 15;
+
+/// Levels of the campaign with their state in the save. Only unlocked levels
+/// can be entered, Left or Reset go back to the start menu
+pub struct LevelSelect {
+    selected: usize,
+    /// First row shown
+    top: usize,
+    /// Levels that can be played, from flash or built in
+    count: usize,
+    progress: Progress,
+}
+
+impl LevelSelect {
+    pub fn new(selected: usize) -> Self {
+        LevelSelect {
+            selected,
+            top: 0,
+            count: 0,
+            progress: Progress::new(),
+        }
+    }
+
+    async fn on_up<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    {
+        self.selected = match self.selected + 1 < self.count {
+            true => self.selected + 1,
+            false => 0,
+        };
+
+        self.redraw(display).await;
+    }
+
+    async fn on_down<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    {
+        self.selected = match self.selected > 0 {
+            true => self.selected - 1,
+            false => self.count.saturating_sub(1),
+        };
+
+        self.redraw(display).await;
+    }
+
+    fn on_select<D, F>(&mut self) -> Option<Box<dyn State<D, F>>>
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+        F: Flash + Send + Sync,
+    {
+        match self.progress.is_unlocked(self.selected) {
+            true => Some(Box::new(Level::new(self.selected))),
+            false => None,
+        }
+    }
+
+    /// Scrolls the list so the selected row is shown
+    fn scroll(&mut self) {
+        if self.selected < self.top {
+            self.top = self.selected;
+        } else if self.selected >= self.top + VISIBLE_ROWS {
+            self.top = self.selected + 1 - VISIBLE_ROWS;
+        }
+    }
+
+    async fn redraw<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Display<u8, Color = Rgb565>,
+    {
+        let settings = settings::current();
+        let palette = settings.theme.palette();
+        let language = settings.language;
+        self.scroll();
+
+        display
+            .draw_solid_area(
+                Rectangle::new(
+                    Point::new(45, 55),
+                    Size::new(320, 15 * VISIBLE_ROWS as u32 + 5),
+                ),
+                palette.menu_bg,
+            )
+            .await;
+
+        display.draw_text(
+            Text::Levels.get(language),
+            Point::new(50, 50),
+            palette.menu_title,
+            None,
+        );
+
+        let last = self.count.min(self.top + VISIBLE_ROWS);
+        for (row, index) in (self.top..last).enumerate() {
+            let y = 70 + 15 * row as i32;
+            let bg = match self.selected == index {
+                true => Some(palette.menu_text_bg),
+                false => None,
+            };
+            let color = match self.progress.is_unlocked(index) {
+                true => palette.menu_text,
+                false => palette.menu_text_disabled,
+            };
+
+            let mut label: String<32> = String::new();
+            write!(&mut label, "{} {}", Text::Level.get(language), index + 1).unwrap();
+            display.draw_text(label.as_str(), Point::new(50, y), color, bg);
+
+            let mut status: String<32> = String::new();
+            match (
+                self.progress.is_unlocked(index),
+                self.progress.best_moves(index),
+            ) {
+                (false, _) => write!(&mut status, "[-] {}", Text::Locked.get(language)),
+                (true, Some(moves)) => {
+                    write!(&mut status, "[+] {} {}", Text::Best.get(language), moves)
+                }
+                (true, None) => write!(&mut status, "[ ]"),
+            }
+            .unwrap();
+            display.draw_text(status.as_str(), Point::new(200, y), color, bg);
+        }
+    }
+}
+
+#[async_trait]
+impl<D, F> State<D, F> for LevelSelect
+where
+    D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    F: Flash + Send + Sync,
+{
+    async fn on_event(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        _flash: &mut F,
+        _tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
+        match event {
+            Event::Button(Buttons::Up(States::Pressed)) => {
+                self.on_up(display).await;
+                None
+            }
+            Event::Button(Buttons::Down(States::Pressed)) => {
+                self.on_down(display).await;
+                None
+            }
+            Event::Button(Buttons::Right(States::Pressed)) => self.on_select::<D, F>(),
+            Event::Button(Buttons::Left(States::Pressed))
+            | Event::Button(Buttons::Reset(States::Pressed)) => Some(Box::new(StartMenu::new())),
+            _ => None,
+        }
+    }
+
+    async fn on_init(&mut self, display: &mut D, flash: &mut F, _tiles: &mut TileCache) {
+        info!("LevelSelect Init");
+        if let Some(data) = SaveSlots::load(flash).await.data() {
+            self.progress = data.progress.clone();
+        }
+
+        // Levels are numbered without gaps, the first missing one ends the list
+        self.count = 0;
+        while self.count < MAX_LEVELS && level_exists(flash, self.count).await {
+            self.count += 1;
+        }
+        self.selected = self.selected.min(self.count.saturating_sub(1));
+
+        display
+            .clear(settings::current().theme.palette().menu_bg)
+            .unwrap();
+
+        self.redraw(display).await;
+    }
+}
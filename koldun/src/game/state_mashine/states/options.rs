@@ -0,0 +1,223 @@
+use crate::game::events::{Buttons, Event, States};
+use crate::game::flash::Flash;
+use crate::game::save::SaveSlots;
+use crate::game::settings::{self, Choice, Settings};
+use crate::game::state_mashine::states::start_menu::StartMenu;
+use crate::game::state_mashine::states::State;
+use crate::game::text::Text;
+use crate::game::tile_cache::TileCache;
+use crate::ili9486::Display;
+use crate::ili9486::GameDisplay;
+use crate::logging::{error, info, Display2Format};
+use alloc::boxed::Box;
+use async_trait::async_trait;
+use core::marker::Send;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+extern crate alloc;
+
+const MAX_ROWS: u16 = 6;
+
+const THEME: u16 = 0;
+const ANIMATION_SPEED: u16 = 1;
+const REPEAT_RATE: u16 = 2;
+const BRIGHTNESS: u16 = 3;
+const LANGUAGE: u16 = 4;
+const BACK: u16 = 5;
+
+/// Settings screen. Changes take effect at once and are saved on the way back
+pub struct Options {
+    row: u16,
+    settings: Settings,
+    slots: Option<SaveSlots>,
+}
+
+impl Options {
+    pub fn new() -> Self {
+        Options {
+            row: 0,
+            settings: settings::current(),
+            slots: None,
+        }
+    }
+
+    async fn on_up<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    {
+        self.row = match self.row < MAX_ROWS - 1 {
+            true => self.row + 1,
+            false => 0,
+        };
+
+        self.redraw(display).await;
+    }
+
+    async fn on_down<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    {
+        self.row = match self.row > 0 {
+            true => self.row - 1,
+            false => MAX_ROWS - 1,
+        };
+
+        self.redraw(display).await;
+    }
+
+    async fn on_select<D, F>(
+        &mut self,
+        display: &mut D,
+        flash: &mut F,
+    ) -> Option<Box<dyn State<D, F>>>
+    where
+        D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+        F: Flash + Send + Sync,
+    {
+        match self.row {
+            THEME => self.settings.theme = self.settings.theme.next(),
+            ANIMATION_SPEED => self.settings.animation_speed = self.settings.animation_speed.next(),
+            REPEAT_RATE => self.settings.repeat_rate = self.settings.repeat_rate.next(),
+            BRIGHTNESS => self.settings.brightness = self.settings.brightness.next(),
+            LANGUAGE => self.settings.language = self.settings.language.next(),
+            _ => {
+                self.save(flash).await;
+                return Some(Box::new(StartMenu::new()));
+            }
+        }
+
+        self.settings.apply(display).await;
+        match self.row {
+            // The whole screen changes, title included
+            THEME | LANGUAGE => self.draw_screen(display).await,
+            _ => self.redraw(display).await,
+        }
+        None
+    }
+
+    /// Stores the settings with the rest of the save, if they changed
+    async fn save<F: Flash>(&mut self, flash: &mut F) {
+        let Some(slots) = &mut self.slots else {
+            return;
+        };
+        let mut data = slots.data().cloned().unwrap_or_default();
+        if data.settings == self.settings {
+            return;
+        }
+
+        data.settings = self.settings;
+        if let Err(err) = slots.store(flash, &data).await {
+            error!("Settings not saved: {}", Display2Format(&err));
+        }
+    }
+
+    async fn draw_screen<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Display<u8, Color = Rgb565>,
+    {
+        display
+            .clear(self.settings.theme.palette().menu_bg)
+            .unwrap();
+
+        self.redraw(display).await;
+    }
+
+    async fn redraw<D>(&mut self, display: &mut D)
+    where
+        D: GameDisplay + Display<u8, Color = Rgb565>,
+    {
+        let palette = self.settings.theme.palette();
+        let language = self.settings.language;
+
+        display
+            .draw_solid_area(
+                Rectangle::new(Point::new(45, 55), Size::new(320, 95)),
+                palette.menu_bg,
+            )
+            .await;
+
+        display.draw_text(
+            Text::Options.get(language),
+            Point::new(50, 50),
+            palette.menu_title,
+            None,
+        );
+
+        let rows = [
+            (Text::Theme, Some(self.settings.theme.name(language))),
+            (
+                Text::AnimationSpeed,
+                Some(self.settings.animation_speed.name(language)),
+            ),
+            (
+                Text::RepeatRate,
+                Some(self.settings.repeat_rate.name(language)),
+            ),
+            (
+                Text::Brightness,
+                Some(self.settings.brightness.name(language)),
+            ),
+            (Text::Language, Some(self.settings.language.name(language))),
+            (Text::Back, None),
+        ];
+
+        for (row, (label, value)) in rows.iter().enumerate() {
+            let y = 70 + 15 * row as i32;
+            let bg = match self.row == row as u16 {
+                true => Some(palette.menu_text_bg),
+                false => None,
+            };
+
+            display.draw_text(
+                label.get(language),
+                Point::new(50, y),
+                palette.menu_text,
+                bg,
+            );
+            if let Some(value) = value {
+                display.draw_text(value, Point::new(200, y), palette.menu_text, bg);
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<D, F> State<D, F> for Options
+where
+    D: GameDisplay + Send + Display<u8, Color = Rgb565>,
+    F: Flash + Send + Sync,
+{
+    async fn on_event(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        flash: &mut F,
+        _tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
+        match event {
+            Event::Button(Buttons::Up(States::Pressed)) => {
+                self.on_up(display).await;
+                None
+            }
+            Event::Button(Buttons::Down(States::Pressed)) => {
+                self.on_down(display).await;
+                None
+            }
+            Event::Button(Buttons::Right(States::Pressed)) => self.on_select(display, flash).await,
+            Event::Button(Buttons::Reset(States::Pressed)) => {
+                self.row = BACK;
+                self.on_select(display, flash).await
+            }
+            _ => None,
+        }
+    }
+
+    async fn on_init(&mut self, display: &mut D, flash: &mut F, _tiles: &mut TileCache) {
+        info!("Options Init");
+        self.settings = settings::current();
+        self.slots = Some(SaveSlots::load(flash).await);
+
+        self.draw_screen(display).await;
+    }
+}
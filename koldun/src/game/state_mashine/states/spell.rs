@@ -1,18 +1,20 @@
 use super::{
-    level::{grid::Grid, level1::Level1, Level, Levels},
+    level::{grid::Grid, Level},
     State,
 };
+use crate::logging::info;
 use crate::{
     game::{
-        colors,
         events::{Buttons, Event, States},
         flash::Flash,
+        settings::{self, Theme},
+        text::Text,
+        tile_cache::TileCache,
     },
     ili9486::{Display, GameDisplay},
 };
 use alloc::boxed::Box;
 use async_trait::async_trait;
-use defmt::info;
 use embedded_graphics::{pixelcolor::Rgb565, prelude::Point};
 use heapless::Vec;
 
@@ -20,7 +22,8 @@ extern crate alloc;
 
 pub const MAX_COMMANDS: usize = 32;
 
-#[derive(Clone, defmt::Format, Debug)]
+#[derive(Clone, Debug)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
 pub enum SpellCommands {
     Left,
     Right,
@@ -32,17 +35,21 @@ pub enum SpellCommands {
 
 pub struct Spell {
     grid: Grid,
-    level: Levels,
+    level: usize,
+    moves: u16,
+    theme: Theme,
     commands: Vec<SpellCommands, MAX_COMMANDS>,
 }
 
 impl Spell {
-    pub fn from_grid(grid: &mut Grid, level: Levels) -> Self {
+    pub fn from_grid(grid: &mut Grid, level: usize, moves: u16, theme: Theme) -> Self {
         let grid = Grid::new_from(grid);
         let commands: Vec<SpellCommands, MAX_COMMANDS> = Vec::new();
         Spell {
             grid,
             level,
+            moves,
+            theme,
             commands,
         }
     }
@@ -54,34 +61,46 @@ where
     D: GameDisplay + Display<u8, Color = Rgb565> + Send,
     F: Flash + Send + Sync,
 {
-    async fn on_event(&mut self, event: Event, _display: &mut D) -> Option<Box<dyn State<D, F>>> {
+    async fn on_event(
+        &mut self,
+        event: Event,
+        _display: &mut D,
+        _flash: &mut F,
+        _tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
         let mut commands: Vec<SpellCommands, MAX_COMMANDS> =
             Vec::from_slice(self.commands.as_slice()).unwrap();
 
         commands.push(SpellCommands::Right).unwrap();
 
         match event {
-            Event::Button(Buttons::Reset(States::Pressed)) => match self.level {
-                Levels::Level1 => {
-                    return Some(Box::new(Level::<Level1>::from_spell(
-                        &mut self.grid,
-                        commands,
-                    )));
-                }
-            },
+            Event::Button(Buttons::Reset(States::Pressed)) => {
+                return Some(Box::new(Level::from_spell(
+                    self.level,
+                    &mut self.grid,
+                    self.moves,
+                    self.theme,
+                    commands,
+                )));
+            }
             _ => return None,
         }
     }
 
-    async fn on_init(&mut self, display: &mut D, _flash: &mut F) {
+    async fn on_init(&mut self, display: &mut D, _flash: &mut F, _tiles: &mut TileCache) {
         info!("Spell screen");
-        display.clear(colors::WALL_BG).unwrap();
+        let palette = self.theme.palette();
+        display.clear(palette.wall_bg).unwrap();
 
         display.draw_text(
-            "Spell screen",
+            Text::SpellScreen.get(settings::current().language),
             Point::new(210, 100),
-            colors::START_MENU_TILE,
+            palette.menu_title,
             None,
         );
     }
+
+    fn level(&self) -> Option<usize> {
+        Some(self.level)
+    }
 }
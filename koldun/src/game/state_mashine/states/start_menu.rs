@@ -1,33 +1,53 @@
-use crate::game::colors;
 use crate::game::events::{Buttons, Event, States};
 use crate::game::flash::Flash;
-use crate::game::state_mashine::states::level::{level1::Level1, Level};
+use crate::game::save::SaveSlots;
+use crate::game::settings;
+use crate::game::state_mashine::states::level::{level_exists, Level};
+use crate::game::state_mashine::states::level_select::LevelSelect;
+use crate::game::state_mashine::states::options::Options;
 use crate::game::state_mashine::states::State;
+use crate::game::text::Text;
+use crate::game::tile_cache::TileCache;
 use crate::ili9486::Display;
 use crate::ili9486::GameDisplay;
+use crate::logging::info;
 use alloc::boxed::Box;
 use async_trait::async_trait;
 use core::marker::Send;
-use defmt::info;
 use embedded_graphics::pixelcolor::Rgb565;
 use embedded_graphics::prelude::{Point, Size};
 use embedded_graphics::primitives::Rectangle;
 extern crate alloc;
 
-const MAX_COMMANDS: u16 = 3;
+const MAX_COMMANDS: u16 = 4;
+
+const CONTINUE: u16 = 1;
+const LEVELS: u16 = 2;
+const OPTIONS: u16 = 3;
 
 pub enum StartMenuCommands {
     NewGame,
+    Continue,
+    Levels,
     Options,
 }
 
 pub struct StartMenu {
     command: u16,
+    /// Furthest unlocked level of the save, `None` greys out Continue
+    continue_level: Option<usize>,
 }
 
 impl StartMenu {
     pub fn new() -> Self {
-        StartMenu { command: 0 }
+        StartMenu {
+            command: 0,
+            continue_level: None,
+        }
+    }
+
+    fn is_enabled(&self, command: u16) -> bool {
+        command != CONTINUE || self.continue_level.is_some()
     }
 
     async fn on_up<D, F>(&mut self, display: &mut D) -> Option<Box<dyn State<D, F>>>
@@ -35,10 +55,15 @@ impl StartMenu {
         D: GameDisplay + Send + Display<u8, Color = Rgb565>,
         F: Flash + Send + Sync,
     {
-        self.command = match self.command < MAX_COMMANDS - 1 {
-            true => self.command + 1,
-            false => 0,
-        };
+        loop {
+            self.command = match self.command < MAX_COMMANDS - 1 {
+                true => self.command + 1,
+                false => 0,
+            };
+            if self.is_enabled(self.command) {
+                break;
+            }
+        }
 
         self.redraw(display).await;
         None
@@ -49,10 +74,15 @@ impl StartMenu {
         D: GameDisplay + Send + Display<u8, Color = Rgb565>,
         F: Flash + Send + Sync,
     {
-        self.command = match self.command > 0 {
-            true => self.command - 1,
-            false => MAX_COMMANDS - 1,
-        };
+        loop {
+            self.command = match self.command > 0 {
+                true => self.command - 1,
+                false => MAX_COMMANDS - 1,
+            };
+            if self.is_enabled(self.command) {
+                break;
+            }
+        }
 
         self.redraw(display).await;
         None
@@ -64,7 +94,15 @@ impl StartMenu {
         F: Flash + Send + Sync,
     {
         match self.command {
-            0 => Some(Box::new(Level::<Level1>::new())),
+            0 => Some(Box::new(Level::new(0))),
+            CONTINUE => match self.continue_level {
+                Some(index) => Some(Box::new(Level::new(index))),
+                None => None,
+            },
+            LEVELS => Some(Box::new(LevelSelect::new(
+                self.continue_level.unwrap_or_default(),
+            ))),
+            OPTIONS => Some(Box::new(Options::new())),
             _ => None,
         }
     }
@@ -73,46 +111,63 @@ impl StartMenu {
     where
         D: GameDisplay + Display<u8, Color = Rgb565>,
     {
+        let settings = settings::current();
+        let palette = settings.theme.palette();
+        let language = settings.language;
+
         display
             .draw_solid_area(
-                Rectangle::new(Point::new(45, 55), Size::new(70, 40)),
-                colors::START_MENU_BG,
+                Rectangle::new(Point::new(45, 55), Size::new(150, 65)),
+                palette.menu_bg,
             )
             .await;
 
         display.draw_text(
-            "KOLDUN the Game",
+            Text::Title.get(language),
             Point::new(50, 50),
-            colors::START_MENU_TILE,
+            palette.menu_title,
             None,
         );
 
         display.draw_text(
-            "New game",
+            Text::NewGame.get(language),
             Point::new(50, 70),
-            colors::START_MENU_TEXT,
+            palette.menu_text,
             match self.command {
-                0 => Some(colors::START_MENU_TEXT_BG),
+                0 => Some(palette.menu_text_bg),
                 _ => None,
             },
         );
 
         display.draw_text(
-            "Continue",
+            Text::Continue.get(language),
             Point::new(50, 85),
-            colors::START_MENU_TEXT,
+            match self.continue_level {
+                Some(_) => palette.menu_text,
+                None => palette.menu_text_disabled,
+            },
             match self.command {
-                1 => Some(colors::START_MENU_TEXT_BG),
+                CONTINUE => Some(palette.menu_text_bg),
                 _ => None,
             },
         );
 
         display.draw_text(
-            "Options",
+            Text::Levels.get(language),
             Point::new(50, 100),
-            colors::START_MENU_TEXT,
+            palette.menu_text,
             match self.command {
-                2 => Some(colors::START_MENU_TEXT_BG),
+                LEVELS => Some(palette.menu_text_bg),
+                _ => None,
+            },
+        );
+
+        display.draw_text(
+            Text::Options.get(language),
+            Point::new(50, 115),
+            palette.menu_text,
+            match self.command {
+                OPTIONS => Some(palette.menu_text_bg),
                 _ => None,
             },
         );
@@ -125,7 +180,13 @@ where
     D: GameDisplay + Send + Display<u8, Color = Rgb565>,
     F: Flash + Send + Sync,
 {
-    async fn on_event(&mut self, event: Event, display: &mut D) -> Option<Box<dyn State<D, F>>> {
+    async fn on_event(
+        &mut self,
+        event: Event,
+        display: &mut D,
+        _flash: &mut F,
+        _tiles: &mut TileCache,
+    ) -> Option<Box<dyn State<D, F>>> {
         match event {
             Event::Button(Buttons::Up(States::Pressed)) => self.on_up::<D, F>(display).await,
             Event::Button(Buttons::Down(States::Pressed)) => self.on_down::<D, F>(display).await,
@@ -134,10 +195,30 @@ where
         }
     }
 
-    async fn on_init(&mut self, display: &mut D, _flash: &mut F) {
+    async fn on_init(&mut self, display: &mut D, flash: &mut F, _tiles: &mut TileCache) {
         info!("StartMenu Init");
-        display.clear(colors::START_MENU_BG).unwrap();
+        self.continue_level = continue_level(flash).await;
+        if !self.is_enabled(self.command) {
+            self.command = 0;
+        }
+        display
+            .clear(settings::current().theme.palette().menu_bg)
+            .unwrap();
 
         self.redraw(display).await;
     }
 }
+
+async fn continue_level<F: Flash>(flash: &mut F) -> Option<usize> {
+    let slots = SaveSlots::load(flash).await;
+    let progress = &slots.data()?.progress;
+    if !progress.has_progress() {
+        return None;
+    }
+    let mut index = progress.unlocked() - 1;
+    // The save may unlock a level that is not flashed
+    while index > 0 && !level_exists(flash, index).await {
+        index -= 1;
+    }
+    Some(index)
+}
@@ -0,0 +1,77 @@
+//! Strings shown by the game, in every `Language`.
+//!
+//! Text is drawn with an ISO 8859-5 font, so the strings may use Latin and
+//! Cyrillic letters but nothing else outside ASCII.
+
+use crate::game::settings::Language;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Text {
+    Title,
+    NewGame,
+    Continue,
+    Levels,
+    Options,
+    Theme,
+    AnimationSpeed,
+    RepeatRate,
+    Brightness,
+    Language,
+    Back,
+    SpellScreen,
+    Level,
+    IsBroken,
+    Locked,
+    Best,
+}
+
+impl Text {
+    pub fn get(&self, language: Language) -> &'static str {
+        match language {
+            Language::English => self.english(),
+            Language::Russian => self.russian(),
+        }
+    }
+
+    fn english(&self) -> &'static str {
+        match self {
+            Text::Title => "KOLDUN the Game",
+            Text::NewGame => "New game",
+            Text::Continue => "Continue",
+            Text::Levels => "Levels",
+            Text::Options => "Options",
+            Text::Theme => "Theme",
+            Text::AnimationSpeed => "Animation",
+            Text::RepeatRate => "Key repeat",
+            Text::Brightness => "Brightness",
+            Text::Language => "Language",
+            Text::Back => "Back",
+            Text::SpellScreen => "Spell screen",
+            Text::Level => "Level",
+            Text::IsBroken => "is broken",
+            Text::Locked => "locked",
+            Text::Best => "best",
+        }
+    }
+
+    fn russian(&self) -> &'static str {
+        match self {
+            Text::Title => "КОЛДУН",
+            Text::NewGame => "Новая игра",
+            Text::Continue => "Продолжить",
+            Text::Levels => "Уровни",
+            Text::Options => "Настройки",
+            Text::Theme => "Тема",
+            Text::AnimationSpeed => "Анимация",
+            Text::RepeatRate => "Повтор",
+            Text::Brightness => "Яркость",
+            Text::Language => "Язык",
+            Text::Back => "Назад",
+            Text::SpellScreen => "Заклинание",
+            Text::Level => "Уровень",
+            Text::IsBroken => "сломан",
+            Text::Locked => "закрыт",
+            Text::Best => "рекорд",
+        }
+    }
+}
@@ -0,0 +1,176 @@
+//! Rendered tiles shared by all the states, kept within a byte budget.
+//!
+//! A rendered tile takes `TILE_BYTES`, sixteen times its 1bpp source and four times
+//! a 4bpp one, so only the most recently drawn ones are kept: a tile that would go over
+//! the budget pushes out the least recently used ones, and those are rendered again
+//! the next time they are drawn. With a budget below a single tile there is no cache
+//! at all, tiles are streamed to the display from their source or put together on a
+//! canvas from it, a full tile is never rendered.
+
+use crate::game::colors::{Palette, TilePalette};
+use crate::game::tiles::{TileId, TILE_SIZE_X, TILE_SIZE_Y};
+use crate::ili9486::Display;
+use alloc::boxed::Box;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::Point;
+use hashbrown::HashMap;
+extern crate alloc;
+
+/// Size of a rendered tile, RGB565
+pub const TILE_BYTES: usize = TILE_SIZE_X * TILE_SIZE_Y * 2;
+
+/// Budget the state machine starts with, 40 tiles out of the 150 KB heap
+pub const DEFAULT_BUDGET: usize = 40 * TILE_BYTES;
+
+/// A tile in the colours it is drawn with, the same tile in two themes is cached twice
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TileKey {
+    pub id: TileId,
+    pub colors: TilePalette,
+}
+
+impl TileKey {
+    pub fn new(id: TileId, colors: TilePalette) -> Self {
+        TileKey { id, colors }
+    }
+
+    /// `id` in the colours it takes from `palette`
+    pub fn themed(id: TileId, palette: &Palette) -> Self {
+        TileKey::new(id, id.colors(palette))
+    }
+
+    /// The colours the tile indexes, two for a 1bpp tile
+    pub fn colors(&self) -> &[Rgb565] {
+        self.colors.colors(1 << self.id.depth())
+    }
+}
+
+struct Entry {
+    data: Box<[u8; TILE_BYTES]>,
+    /// Value of the clock when the tile was last drawn
+    used: u64,
+}
+
+pub struct TileCache {
+    budget: usize,
+    tiles: HashMap<TileKey, Entry>,
+    /// Counts lookups, orders the tiles by their last use
+    clock: u64,
+    hits: u32,
+    misses: u32,
+}
+
+impl TileCache {
+    pub fn new(budget: usize) -> Self {
+        TileCache {
+            budget,
+            tiles: HashMap::new(),
+            clock: 0,
+            hits: 0,
+            misses: 0,
+        }
+    }
+
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+
+    /// Changes the budget, dropping the tiles that no longer fit
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+        self.make_room(budget);
+        self.tiles.shrink_to_fit();
+    }
+
+    /// Bytes taken by the rendered tiles
+    pub fn used(&self) -> usize {
+        self.tiles.len() * TILE_BYTES
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+
+    pub fn contains(&self, key: &TileKey) -> bool {
+        self.tiles.contains_key(key)
+    }
+
+    /// Lookups that found the tile rendered
+    pub fn hits(&self) -> u32 {
+        self.hits
+    }
+
+    /// Lookups that had to render the tile
+    pub fn misses(&self) -> u32 {
+        self.misses
+    }
+
+    pub fn clear(&mut self) {
+        self.tiles.clear();
+        self.tiles.shrink_to_fit();
+    }
+
+    /// The tile rendered, from the cache or rendered now. The tile asked for is
+    /// always kept, even when the budget is smaller than a single tile
+    pub fn get(&mut self, key: TileKey) -> &[u8; TILE_BYTES] {
+        self.clock += 1;
+        match self.tiles.contains_key(&key) {
+            true => self.hits = self.hits.saturating_add(1),
+            false => {
+                self.misses = self.misses.saturating_add(1);
+                self.make_room(self.budget.saturating_sub(TILE_BYTES));
+                let data = Box::new(key.id.render(key.colors()));
+                self.tiles.insert(key, Entry { data, used: 0 });
+            }
+        }
+
+        let entry = self.tiles.get_mut(&key).unwrap();
+        entry.used = self.clock;
+        &entry.data
+    }
+
+    /// `id` in the colours of `palette`
+    pub fn themed(&mut self, id: TileId, palette: &Palette) -> &[u8; TILE_BYTES] {
+        self.get(TileKey::themed(id, palette))
+    }
+
+    /// `false` if the budget holds no tile, tiles are streamed instead
+    pub fn is_enabled(&self) -> bool {
+        self.budget >= TILE_BYTES
+    }
+
+    /// Draws the tile at `origin`, from the cache or streamed when it is disabled
+    pub async fn draw<D>(&mut self, display: &mut D, origin: Point, key: TileKey)
+    where
+        D: Display<u8, Color = Rgb565> + Send,
+    {
+        match self.is_enabled() {
+            true => display.draw_tile(origin, self.get(key)).await,
+            false => {
+                let (bits, depth) = (key.id.bits(), key.id.depth());
+                display
+                    .draw_tile_bits(origin, bits, depth, key.colors())
+                    .await
+            }
+        }
+    }
+
+    /// Drops the least recently used tiles until no more than `bytes` are taken
+    fn make_room(&mut self, bytes: usize) {
+        while self.used() > bytes {
+            let oldest = self
+                .tiles
+                .iter()
+                .min_by_key(|(_, entry)| entry.used)
+                .map(|(key, _)| *key);
+            match oldest {
+                Some(key) => self.tiles.remove(&key),
+                None => break,
+            };
+        }
+    }
+}
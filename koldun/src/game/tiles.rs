@@ -1,10 +1,15 @@
-use embedded_graphics::pixelcolor::raw::ToBytes;
+use crate::game::colors::{Ink, Palette, TilePalette};
+use crate::ili9486::expand_bits;
+use core::fmt;
 use embedded_graphics::pixelcolor::Rgb565;
 use koldun_macro_derive::render_tiles;
 
 pub const TILE_SIZE_X: usize = 32;
 pub const TILE_SIZE_Y: usize = 32;
 
+/// Size of a tile's mask, a bit for each pixel
+pub const MASK_BYTES: usize = TILE_SIZE_X * TILE_SIZE_Y / 8;
+
 pub const EMPTY: (usize, usize) = (0, 0);
 pub const FLOOR: (usize, usize) = (0, 1);
 pub const GROUND1: (usize, usize) = (0, 2);
@@ -41,6 +46,7 @@ pub const TREES: (usize, usize) = (1, 19);
 pub const MUSHROOM: (usize, usize) = (1, 20);
 pub const MUSHROOMS: (usize, usize) = (1, 21);
 pub const WEB: (usize, usize) = (1, 22);
+pub const WATER: (usize, usize) = (1, 23);
 
 pub const WIZARD_IDLE1: (usize, usize) = (2, 0);
 pub const WIZARD_IDLE2: (usize, usize) = (2, 1);
@@ -55,89 +61,169 @@ pub const WIZARD_RIGHT2: (usize, usize) = (2, 9);
 pub const SPIDER1: (usize, usize) = (2, 30);
 pub const SPIDER2: (usize, usize) = (2, 31);
 
-pub const TILEMAPS: [&[u8; 4096]; 3] = [
-    include_bytes!("..\\..\\resources\\tiles\\compressed\\tiles0.bin"),
-    include_bytes!("..\\..\\resources\\tiles\\compressed\\tiles1.bin"),
-    include_bytes!("..\\..\\resources\\tiles\\compressed\\tiles2.bin"),
+pub const COBBLES: (usize, usize) = (3, 0);
+pub const PILLAR: (usize, usize) = (3, 1);
+
+/// How a tile takes part in the game, declared in `#[render_tiles]`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub struct TileMeta {
+    /// Grid layer the tile is placed on as terrain, items move on layer 1
+    pub layer: usize,
+    /// Can be stepped on
+    pub walkable: bool,
+    /// Stops spells from being cast into the cell
+    pub blocks_spells: bool,
+    /// Can be entered, but the wizard stepping in loses and the level starts over
+    pub hazard: bool,
+    /// Pixels of value 0 let the layers below show through, as for sprites
+    pub transparent: bool,
+    /// Next frame of the tile's animation
+    pub animated_with: Option<TileId>,
+    pub default_fg: Ink,
+    pub default_bg: Ink,
+}
+
+impl TileMeta {
+    pub const FLOOR: TileMeta = TileMeta {
+        layer: 0,
+        walkable: true,
+        blocks_spells: false,
+        hazard: false,
+        transparent: false,
+        animated_with: None,
+        default_fg: Ink::WallFg,
+        default_bg: Ink::WallBg,
+    };
+}
+
+/// Tile sheets, up to 32 tiles each at the depth `TileId::SHEET_DEPTHS` gives them
+pub const TILEMAPS: [&[u8]; 4] = [
+    include_bytes!("../../resources/tiles/compressed/tiles0.bin"),
+    include_bytes!("../../resources/tiles/compressed/tiles1.bin"),
+    include_bytes!("../../resources/tiles/compressed/tiles2.bin"),
+    include_bytes!("../../resources/tiles/compressed/tiles3.bin"),
 ];
 
 #[render_tiles(
+    sheets = [1, 1, 1, 4],
     EMPTY,
     FLOOR,
     GROUND1,
     GROUND2,
     DOOR_OPEN,
-    CHAIR,
+    CHAIR { walkable = false },
     DEBRIS1,
     DEBRIS2,
     BRIDGE_WOOD1,
     BRIDGE_WOOD2,
-    EXIT_OPEN,
-    EXIT_CLOSED,
-    WALL1,
-    WALL2,
-    WALL3,
-    WALL4,
-    BRICK_WALL1,
-    BRICK_WALL2,
-    BRICK_WALL3,
-    PLATE_WALL1,
-    PLATE_WALL2,
-    STONE1,
-    STONE2,
-    STONE3,
-    FENCE,
-    DOOR_FENCE,
-    DOOR_WOOD,
-    TABLE,
-    PINES,
-    PINE,
-    TREE,
-    TREES,
-    MUSHROOM,
-    MUSHROOMS,
-    WEB,
-    WIZARD_IDLE1,
-    WIZARD_IDLE2,
-    WIZARD_DOWN1,
-    WIZARD_DOWN2,
-    WIZARD_UP1,
-    WIZARD_UP2,
-    WIZARD_LEFT1,
-    WIZARD_LEFT2,
-    WIZARD_RIGHT1,
-    WIZARD_RIGHT2,
-    SPIDER1,
-    SPIDER2
+    EXIT_OPEN { transparent = true, default_fg = WizardFg },
+    EXIT_CLOSED { transparent = true, default_fg = WizardFg },
+    WALL1 { layer = 1, walkable = false, blocks_spells = true },
+    WALL2 { layer = 1, walkable = false, blocks_spells = true },
+    WALL3 { layer = 1, walkable = false, blocks_spells = true },
+    WALL4 { layer = 1, walkable = false, blocks_spells = true },
+    BRICK_WALL1 { layer = 1, walkable = false, blocks_spells = true },
+    BRICK_WALL2 { layer = 1, walkable = false, blocks_spells = true },
+    BRICK_WALL3 { layer = 1, walkable = false, blocks_spells = true },
+    PLATE_WALL1 { layer = 1, walkable = false, blocks_spells = true },
+    PLATE_WALL2 { layer = 1, walkable = false, blocks_spells = true },
+    STONE1 { layer = 1, walkable = false, blocks_spells = true },
+    STONE2 { layer = 1, walkable = false, blocks_spells = true },
+    STONE3 { layer = 1, walkable = false, blocks_spells = true },
+    FENCE { walkable = false, default_fg = WizardFg },
+    DOOR_FENCE { walkable = false },
+    DOOR_WOOD { layer = 1, walkable = false, blocks_spells = true },
+    TABLE { walkable = false },
+    PINES { layer = 1, walkable = false, blocks_spells = true },
+    PINE { layer = 1, walkable = false, blocks_spells = true },
+    TREE { layer = 1, walkable = false, blocks_spells = true },
+    TREES { layer = 1, walkable = false, blocks_spells = true },
+    MUSHROOM { walkable = false },
+    MUSHROOMS { walkable = false },
+    WEB { hazard = true },
+    WATER { hazard = true },
+    WIZARD_IDLE1 { layer = 1, transparent = true, animated_with = WIZARD_IDLE2, default_fg = WizardFg },
+    WIZARD_IDLE2 { layer = 1, transparent = true, animated_with = WIZARD_IDLE1, default_fg = WizardFg },
+    WIZARD_DOWN1 { layer = 1, transparent = true, animated_with = WIZARD_DOWN2, default_fg = WizardFg },
+    WIZARD_DOWN2 { layer = 1, transparent = true, animated_with = WIZARD_DOWN1, default_fg = WizardFg },
+    WIZARD_UP1 { layer = 1, transparent = true, animated_with = WIZARD_UP2, default_fg = WizardFg },
+    WIZARD_UP2 { layer = 1, transparent = true, animated_with = WIZARD_UP1, default_fg = WizardFg },
+    WIZARD_LEFT1 { layer = 1, transparent = true, animated_with = WIZARD_LEFT2, default_fg = WizardFg },
+    WIZARD_LEFT2 { layer = 1, transparent = true, animated_with = WIZARD_LEFT1, default_fg = WizardFg },
+    WIZARD_RIGHT1 { layer = 1, transparent = true, animated_with = WIZARD_RIGHT2, default_fg = WizardFg },
+    WIZARD_RIGHT2 { layer = 1, transparent = true, animated_with = WIZARD_RIGHT1, default_fg = WizardFg },
+    SPIDER1 { layer = 1, transparent = true, animated_with = SPIDER2, default_fg = WizardFg },
+    SPIDER2 { layer = 1, transparent = true, animated_with = SPIDER1, default_fg = WizardFg },
+    COBBLES,
+    PILLAR { layer = 1, walkable = false, blocks_spells = true }
 )]
 pub struct Tile {}
 
-impl Tile {
-    fn render(data: &[u8; 128], fg: Rgb565, bg: Rgb565) -> [u8; 32 * 32 * 2] {
-        let mut colors = [0; 32 * 32 * 2];
-        for (i, byte) in data.iter().enumerate() {
-            for j in (0..8).rev() {
-                let bit = (byte >> j) & 1;
-
-                let c: [u8; 2];
-                match bit == 0 {
-                    true => {
-                        c = Tile::color_to_data(bg);
-                    }
-                    false => {
-                        c = Tile::color_to_data(fg);
-                    }
-                }
-                let start = (i * 16) + j * 2;
-                let end = start + 2;
-                colors[start..end].copy_from_slice(&c);
+impl TileId {
+    /// Colours the tile is drawn with in `palette`: the pair its metadata picks for a
+    /// two-colour tile, the palette's tile colours for an indexed one
+    pub fn colors(&self, palette: &Palette) -> TilePalette {
+        let meta = self.meta();
+        match self.depth() {
+            1 => TilePalette::pair(palette.ink(meta.default_bg), palette.ink(meta.default_fg)),
+            _ => palette.tiles,
+        }
+    }
+
+    /// Pixels the tile covers when drawn over other layers, a bit for each set the same
+    /// way round as a 1bpp tile: all of them, or for a transparent tile the ones not
+    /// of value 0
+    pub fn mask(&self) -> [u8; MASK_BYTES] {
+        let mut mask = [0xff; MASK_BYTES];
+        if !self.meta().transparent {
+            return mask;
+        }
+
+        let (bits, depth) = (self.bits(), self.depth());
+        for pixel in 0..TILE_SIZE_X * TILE_SIZE_Y {
+            let value = (bits[pixel * depth / 8] >> (pixel * depth % 8)) & ((1 << depth) - 1);
+            if value == 0 {
+                mask[pixel / 8] &= !(1 << (pixel % 8));
             }
         }
-        colors
+        mask
+    }
+
+    /// Renders the tile in the colours of `palette`
+    pub fn themed(&self, palette: &Palette) -> [u8; 32 * 32 * 2] {
+        self.render(&self.colors(palette).0)
     }
+}
 
-    fn color_to_data(color: Rgb565) -> [u8; 2] {
-        let b = color.to_ne_bytes();
-        [b[1], b[0]]
+impl fmt::Debug for TileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[cfg(feature = "rp2040")]
+impl defmt::Format for TileId {
+    fn format(&self, f: defmt::Formatter) {
+        defmt::write!(f, "{=str}", self.name())
+    }
+}
+
+/// A tile id no `TileId` has
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "rp2040", derive(defmt::Format))]
+pub struct UnknownTile(pub u16);
+
+impl fmt::Display for UnknownTile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Unknown tile {}", self.0)
+    }
+}
+
+impl Tile {
+    fn render(data: &[u8], depth: usize, colors: &[Rgb565]) -> [u8; 32 * 32 * 2] {
+        let mut pixels = [0; 32 * 32 * 2];
+        expand_bits(data, depth, colors, &mut pixels);
+        pixels
     }
 }
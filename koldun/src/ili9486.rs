@@ -5,8 +5,9 @@ use core::convert::Infallible;
 use core::slice::SlicePattern;
 use embassy_futures::block_on;
 use embedded_graphics::draw_target::DrawTarget;
-use embedded_graphics::mono_font::ascii::FONT_9X15_BOLD;
+use embedded_graphics::mono_font::iso_8859_5::FONT_9X15_BOLD;
 use embedded_graphics::mono_font::MonoTextStyle;
+use embedded_graphics::pixelcolor::raw::RawU16;
 use embedded_graphics::pixelcolor::{BinaryColor, Rgb565};
 use embedded_graphics::prelude::*;
 use embedded_graphics::prelude::{Dimensions, Point, Size};
@@ -17,8 +18,14 @@ use heapless::Vec;
 use tinytga::Tga;
 extern crate alloc;
 
+pub mod gram;
+#[cfg(feature = "host")]
+pub mod mock;
 pub mod pio_parallel;
 
+/// Rows of a tile expanded at a time by `draw_tile_bits`
+pub const STREAM_ROWS: usize = 4;
+
 pub enum PixelFormat {
     Bit16 = 0b0101_0101,
     Bit18 = 0b0110_0110,
@@ -69,10 +76,20 @@ pub trait Display<DataFormat> {
     async fn norma_display_mode(&mut self);
     async fn display_on(&mut self);
     async fn idle_mode_off(&mut self);
+    async fn set_brightness(&mut self, value: u8);
     async fn draw_data(&mut self, area: Rectangle, data: &[DataFormat]);
     async fn draw_solid(&mut self, origin: Point, color: Self::Color);
     async fn draw_solid_area(&mut self, area: Rectangle, color: Self::Color);
     async fn draw_tile(&mut self, origin: Point, data: &[DataFormat]);
+    /// Draws a 32x32 tile from its source of `depth` bits per pixel, expanding
+    /// `STREAM_ROWS` rows at a time instead of rendering the whole tile first
+    async fn draw_tile_bits(
+        &mut self,
+        origin: Point,
+        bits: &[u8],
+        depth: usize,
+        colors: &[Self::Color],
+    );
     async fn tearing_effect_line_on(&mut self);
     async fn column_address_set(&mut self, start: u16, end: u16);
     async fn page_address_set(&mut self, start: u16, end: u16);
@@ -95,6 +112,14 @@ where
         Ili9486 { pio_interface }
     }
 
+    pub fn interface(&self) -> &C {
+        &self.pio_interface
+    }
+
+    pub fn interface_mut(&mut self) -> &mut C {
+        &mut self.pio_interface
+    }
+
     fn color_to_data(color: Rgb565) -> [u8; 2] {
         let b = color.to_ne_bytes();
         [b[1], b[0]]
@@ -170,6 +195,10 @@ where
             for y in (0..320).step_by(32) {
                 let square = Rectangle::new(Point::new(x, y), Size::new(32, 32));
                 let area = area.intersection(&square);
+                // An empty area sets no window, the data would land in the previous one
+                if area.is_zero_sized() {
+                    continue;
+                }
 
                 block_on(self.draw_data(area, v.as_slice()));
             }
@@ -231,6 +260,16 @@ impl<C: PioParallel<u8> + Send> Display<u8> for Ili9486<C> {
             .await;
     }
 
+    async fn set_brightness(&mut self, value: u8) {
+        // Brightness control has to be enabled in CTRL Display first (BCTRL | BL)
+        self.pio_interface
+            .write_command(Command::WriteCTRLDisplayValue, &[0b0010_0100])
+            .await;
+        self.pio_interface
+            .write_command(Command::WriteDisplayBrightnessValue, &[value])
+            .await;
+    }
+
     async fn inversion_off(&mut self) {
         self.pio_interface
             .write_command(Command::DisplayInversionOff, &[])
@@ -324,6 +363,27 @@ impl<C: PioParallel<u8> + Send> Display<u8> for Ili9486<C> {
             .await;
     }
 
+    async fn draw_tile_bits(
+        &mut self,
+        origin: Point,
+        bits: &[u8],
+        depth: usize,
+        colors: &[Self::Color],
+    ) {
+        let area = Rectangle::new(origin, Size::new(32, 32));
+        self.set_active_area(area).await;
+
+        // The first chunk starts at the window origin, the rest carry on after it
+        let mut buffer = [0u8; STREAM_ROWS * 32 * 2];
+        let mut command = Command::MemoryWrite;
+        for chunk in bits.chunks(STREAM_ROWS * 32 * depth / 8) {
+            let data = &mut buffer[..chunk.len() * 16 / depth];
+            expand_bits(chunk, depth, colors, data);
+            self.pio_interface.write_command(command, data).await;
+            command = Command::MemoryWriteContinue;
+        }
+    }
+
     async fn tearing_effect_line_on(&mut self) {
         self.pio_interface
             .write_command(Command::TearingEffectLineOn, &[0b1])
@@ -380,7 +440,24 @@ impl<C: PioParallel<u8> + Send> Display<u8> for Ili9486<C> {
 #[async_trait]
 impl<C: PioParallel<u8> + Send> GameDisplay for Ili9486<C> {}
 
-#[derive(Clone, Copy, Debug)]
+/// Expands pixels of `depth` bits (1, 2 or 4) into big endian RGB565, each in the
+/// colour of `colors` its value indexes: `[bg, fg]` for a 1bpp tile. The lowest bits
+/// of a byte are its leftmost pixel, as in the tile sheets.
+/// `data` takes `16 / depth` bytes for every byte of `bits`
+pub fn expand_bits(bits: &[u8], depth: usize, colors: &[Rgb565], data: &mut [u8]) {
+    let mask = (1 << depth) - 1;
+    let values = bits.iter().flat_map(|byte| {
+        (0..8)
+            .step_by(depth)
+            .map(move |shift| (byte >> shift) & mask)
+    });
+    for (value, pixel) in values.zip(data.chunks_exact_mut(2)) {
+        let color = RawU16::from(colors[value as usize]).into_inner();
+        pixel.copy_from_slice(&color.to_be_bytes());
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub enum Command {
     Nop = 0x00,
     SoftReset = 0x01,
@@ -0,0 +1,202 @@
+use crate::ili9486::{Command, PixelFormat};
+use alloc::vec;
+use alloc::vec::Vec;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+extern crate alloc;
+
+pub const WIDTH: usize = 480;
+pub const HEIGHT: usize = 320;
+
+/// Model of the ILI9486 frame memory, in the logical orientation `main` configures.
+///
+/// Pixel data goes through the same path as on the controller: an address window
+/// set by `ColumnAddressSet`/`PageAddressSet`, then big endian RGB565 words
+/// written row by row, wrapping back to the window start when it is full.
+/// Pixels outside of the panel are dropped.
+pub struct Gram {
+    pixels: Vec<u16>,
+    columns: (u16, u16),
+    pages: (u16, u16),
+    cursor: (u16, u16),
+    madctl: u8,
+    pixel_format: u8,
+    display_on: bool,
+    brightness: u8,
+}
+
+impl Gram {
+    pub fn new() -> Self {
+        Gram {
+            pixels: vec![0; WIDTH * HEIGHT],
+            columns: (0, WIDTH as u16 - 1),
+            pages: (0, HEIGHT as u16 - 1),
+            cursor: (0, 0),
+            madctl: 0,
+            pixel_format: PixelFormat::Bit16 as u8,
+            display_on: false,
+            brightness: 0,
+        }
+    }
+
+    /// Decodes a command as the controller receives it, parameters included.
+    /// Commands that don't touch the frame memory, its window or the state
+    /// kept below are ignored.
+    pub fn apply(&mut self, command: Command, data: &[u8]) {
+        match command {
+            Command::ColumnAddressSet => {
+                if let Some((start, end)) = address_range(data) {
+                    self.column_address_set(start, end);
+                }
+            }
+            Command::PageAddressSet => {
+                if let Some((start, end)) = address_range(data) {
+                    self.page_address_set(start, end);
+                }
+            }
+            Command::MemoryWrite => self.memory_write(data),
+            Command::MemoryWriteContinue => self.memory_write_continue(data),
+            Command::MemoryAccessControl => {
+                if let Some(madctl) = data.first() {
+                    self.madctl = *madctl;
+                }
+            }
+            Command::InterfacePixelFormat => {
+                if let Some(format) = data.first() {
+                    self.pixel_format = *format;
+                }
+            }
+            Command::DisplayOn => self.display_on = true,
+            Command::DisplayOff => self.display_on = false,
+            Command::WriteDisplayBrightnessValue => {
+                if let Some(brightness) = data.first() {
+                    self.brightness = *brightness;
+                }
+            }
+            _ => (),
+        }
+    }
+
+    pub fn column_address_set(&mut self, start: u16, end: u16) {
+        self.columns = (start, end);
+    }
+
+    pub fn page_address_set(&mut self, start: u16, end: u16) {
+        self.pages = (start, end);
+    }
+
+    /// Writes from the start of the window
+    pub fn memory_write(&mut self, data: &[u8]) {
+        self.cursor = (self.columns.0, self.pages.0);
+        self.memory_write_continue(data);
+    }
+
+    /// Writes from where the previous write stopped
+    pub fn memory_write_continue(&mut self, data: &[u8]) {
+        let (start_x, end_x) = self.columns;
+        let (start_y, end_y) = self.pages;
+        let (mut x, mut y) = self.cursor;
+
+        for word in data.chunks_exact(2) {
+            if (x as usize) < WIDTH && (y as usize) < HEIGHT {
+                self.pixels[y as usize * WIDTH + x as usize] =
+                    u16::from_be_bytes([word[0], word[1]]);
+            }
+
+            x = x.wrapping_add(1);
+            if x > end_x || x == 0 {
+                x = start_x;
+                y = y.wrapping_add(1);
+                if y > end_y || y == 0 {
+                    y = start_y;
+                }
+            }
+        }
+        self.cursor = (x, y);
+    }
+
+    pub fn set_madctl(&mut self, madctl: u8) {
+        self.madctl = madctl;
+    }
+
+    pub fn set_pixel_format(&mut self, pixel_format: u8) {
+        self.pixel_format = pixel_format;
+    }
+
+    pub fn set_display_on(&mut self, on: bool) {
+        self.display_on = on;
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    /// Sets a pixel directly, bypassing the address window
+    pub fn set_pixel(&mut self, x: usize, y: usize, color: Rgb565) {
+        if x < WIDTH && y < HEIGHT {
+            self.pixels[y * WIDTH + x] = RawU16::from(color).into_inner();
+        }
+    }
+
+    pub fn pixel(&self, x: usize, y: usize) -> Rgb565 {
+        Rgb565::from(RawU16::new(self.pixels[y * WIDTH + x]))
+    }
+
+    /// Raw RGB565 values, row by row
+    pub fn raw(&self) -> &[u16] {
+        &self.pixels
+    }
+
+    /// Current address window as `((start_x, end_x), (start_y, end_y))`
+    pub fn window(&self) -> ((u16, u16), (u16, u16)) {
+        (self.columns, self.pages)
+    }
+
+    /// Value last sent with `MemoryAccessControl`
+    pub fn madctl(&self) -> u8 {
+        self.madctl
+    }
+
+    pub fn pixel_format(&self) -> u8 {
+        self.pixel_format
+    }
+
+    pub fn is_on(&self) -> bool {
+        self.display_on
+    }
+
+    /// Value last sent with `WriteDisplayBrightnessValue`
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    /// Frame as 8 bit RGB triplets, row by row
+    pub fn to_rgb888(&self) -> Vec<u8> {
+        let mut rgb = Vec::with_capacity(WIDTH * HEIGHT * 3);
+        for raw in self.pixels.iter() {
+            let color = Rgb565::from(RawU16::new(*raw));
+            rgb.push((color.r() << 3) | (color.r() >> 2));
+            rgb.push((color.g() << 2) | (color.g() >> 4));
+            rgb.push((color.b() << 3) | (color.b() >> 2));
+        }
+        rgb
+    }
+}
+
+impl Default for Gram {
+    fn default() -> Self {
+        Gram::new()
+    }
+}
+
+/// Start and end address from `[start_hi, start_lo, end_hi, end_lo]`
+fn address_range(data: &[u8]) -> Option<(u16, u16)> {
+    match data {
+        [start_hi, start_lo, end_hi, end_lo, ..] => Some((
+            u16::from_be_bytes([*start_hi, *start_lo]),
+            u16::from_be_bytes([*end_hi, *end_lo]),
+        )),
+        _ => None,
+    }
+}
@@ -0,0 +1,51 @@
+use crate::ili9486::gram::Gram;
+use crate::ili9486::pio_parallel::PioParallel;
+use crate::ili9486::Command;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use async_trait::async_trait;
+extern crate alloc;
+
+/// `PioParallel` test double, keeps every command with its parameters
+/// instead of driving the bus.
+pub struct MockParallel {
+    commands: Vec<(Command, Vec<u8>)>,
+}
+
+impl MockParallel {
+    pub fn new() -> Self {
+        MockParallel {
+            commands: Vec::new(),
+        }
+    }
+
+    pub fn commands(&self) -> &[(Command, Vec<u8>)] {
+        &self.commands
+    }
+
+    pub fn clear(&mut self) {
+        self.commands.clear();
+    }
+
+    /// Frame memory after all recorded commands, as the controller would hold it
+    pub fn gram(&self) -> Gram {
+        let mut gram = Gram::new();
+        for (command, data) in self.commands.iter() {
+            gram.apply(*command, data);
+        }
+        gram
+    }
+}
+
+impl Default for MockParallel {
+    fn default() -> Self {
+        MockParallel::new()
+    }
+}
+
+#[async_trait]
+impl PioParallel<u8> for MockParallel {
+    async fn write_command(&mut self, command: Command, words: &[u8]) {
+        self.commands.push((command, Vec::from(words)));
+    }
+}
@@ -1,14 +1,20 @@
 use crate::ili9486::Command;
 use alloc::boxed::Box;
 use async_trait::async_trait;
+#[cfg(feature = "rp2040")]
 use embassy_rp::dma::{AnyChannel, Channel};
+#[cfg(feature = "rp2040")]
 use embassy_rp::gpio::Level;
+#[cfg(feature = "rp2040")]
 use embassy_rp::pio::{
     Common, Config, Direction, FifoJoin, Instance, PioPin, ShiftConfig, ShiftDirection,
     StateMachine,
 };
+#[cfg(feature = "rp2040")]
 use embassy_rp::{into_ref, Peripheral, PeripheralRef};
+#[cfg(feature = "rp2040")]
 use fixed::types::U24F8;
+#[cfg(feature = "rp2040")]
 use heapless::Vec;
 extern crate alloc;
 
@@ -17,11 +23,13 @@ pub trait PioParallel<DataFormat> {
     async fn write_command(&mut self, command: Command, words: &[DataFormat]);
 }
 
+#[cfg(feature = "rp2040")]
 pub struct PioParallel8<'a, P: Instance, const N: usize> {
     dma: PeripheralRef<'a, AnyChannel>,
     sm: StateMachine<'a, P, N>,
 }
 
+#[cfg(feature = "rp2040")]
 impl<'a, P: Instance, const N: usize> PioParallel8<'a, P, N> {
     pub fn new(
         pio: &mut Common<'a, P>,
@@ -108,6 +116,7 @@ impl<'a, P: Instance, const N: usize> PioParallel8<'a, P, N> {
     }
 }
 
+#[cfg(feature = "rp2040")]
 #[async_trait]
 impl<'a, P: Instance + Send, const N: usize> PioParallel<u8> for PioParallel8<'a, P, N> {
     async fn write_command(&mut self, command: Command, words: &[u8]) {
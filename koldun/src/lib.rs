@@ -1,5 +1,5 @@
 #![no_std]
-#![no_main]
+#![cfg_attr(not(test), no_main)]
 #![feature(async_fn_in_trait)]
 #![feature(slice_pattern)]
 #![feature(type_alias_impl_trait)]
@@ -10,9 +10,15 @@
 #![feature(slice_flatten)]
 #![feature(exclusive_range_pattern)]
 
+#[cfg(feature = "host")]
+extern crate std;
+
+pub mod framebuffer;
 pub mod game;
+#[cfg(feature = "rp2040")]
 pub mod heap;
 pub mod ili9486;
+pub mod logging;
 
 #[macro_export]
 macro_rules! h_vec {
@@ -0,0 +1,17 @@
+//! Logging that works on both targets: `defmt` on the device, the `log` facade on the host.
+
+#[cfg(feature = "rp2040")]
+pub use defmt::{debug, error, info, warn, Display2Format};
+
+#[cfg(not(feature = "rp2040"))]
+pub use log::{debug, error, info, warn};
+
+#[cfg(not(feature = "rp2040"))]
+pub struct Display2Format<'a, T: core::fmt::Display + ?Sized>(pub &'a T);
+
+#[cfg(not(feature = "rp2040"))]
+impl<T: core::fmt::Display + ?Sized> core::fmt::Display for Display2Format<'_, T> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        self.0.fmt(f)
+    }
+}
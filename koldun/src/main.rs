@@ -6,10 +6,11 @@
 use defmt::*;
 use defmt_rtt as _;
 use embassy_executor::Spawner;
+use embassy_futures::select::{select, Either};
 use embassy_rp::bind_interrupts;
 use embassy_rp::flash::Flash as RPFlash;
 use embassy_rp::gpio::Pull;
-use embassy_rp::gpio::{Input, Level, Output};
+use embassy_rp::gpio::{Input, Level, Output, Pin};
 use embassy_rp::peripherals::{PIN_10, PIN_11, PIN_12, PIN_13, PIN_26, PIO0};
 use embassy_rp::pio::{InterruptHandler, Pio};
 use embassy_sync::blocking_mutex::raw::ThreadModeRawMutex;
@@ -28,7 +29,9 @@ use embedded_graphics::{
 // use heapless::Vec;
 use koldun::game::events::{Buttons, Event, States};
 // use koldun::game::colors;
-use koldun::game::flash::FlashAccess;
+use koldun::game::flash::{FlashAccess, REPLAY_OFFSET};
+use koldun::game::replay::{Replay, ReplayError};
+use koldun::game::settings;
 use koldun::game::state_mashine::StateMachine;
 use koldun::heap;
 use koldun::ili9486::{pio_parallel::PioParallel8, Display, Ili9486, Order, PixelFormat};
@@ -154,7 +157,8 @@ async fn main(spawner: Spawner) {
     Timer::after(Duration::from_millis(50)).await;
 
     let flash = RPFlash::new(p.FLASH, p.DMA_CH1);
-    let flash = FlashAccess::new(flash);
+    let mut flash = FlashAccess::new(flash);
+    let replay = Replay::load(&mut flash, REPLAY_OFFSET).await;
 
     let mut sm = StateMachine::new(display, flash);
     sm.on_control(Event::Button(Buttons::Down(States::Pressed)))
@@ -162,6 +166,25 @@ async fn main(spawner: Spawner) {
     sm.on_control(Event::Button(Buttons::Right(States::Pressed)))
         .await;
 
+    // A recording flashed at REPLAY_OFFSET is played before the buttons take over
+    match replay {
+        Ok(replay) => {
+            info!("Replaying level {}", replay.level() + 1);
+            // Ticks as far apart as `timer_task` sends them
+            let period = Duration::from_hz(settings::current().animation_speed.tick_hz());
+            let mut next = Instant::now();
+            let wait = || {
+                next += period;
+                Timer::at(next)
+            };
+            if let Err(err) = sm.replay_paced(&replay, wait).await {
+                warn!("Replay not played: {}", Display2Format(&err));
+            }
+        }
+        Err(ReplayError::BadMagic) => (),
+        Err(err) => warn!("Replay is broken: {}", Display2Format(&err)),
+    }
+
     // let mut c = 0;
     // let mut ticker = Ticker::every(Duration::from_hz(10));
     loop {
@@ -216,11 +239,17 @@ async fn main(spawner: Spawner) {
 async fn button_up_task(_spawner: Spawner, mut up: Input<'static, PIN_13>) {
     loop {
         up.wait_for_any_edge().await;
-        let message = match up.is_high() {
+        let pressed = up.is_high();
+        let message = match pressed {
             true => Event::Button(Buttons::Up(States::Pressed)),
             false => Event::Button(Buttons::Up(States::Released)),
         };
-        CONTROL_CHANNEL.send(message).await
+        CONTROL_CHANNEL.send(message).await;
+        if pressed && repeat_while_held(&mut up, Buttons::Up).await {
+            CONTROL_CHANNEL
+                .send(Event::Button(Buttons::Up(States::Released)))
+                .await
+        }
     }
 }
 
@@ -237,11 +266,17 @@ async fn button_down_task(_spawner: Spawner, mut down: Input<'static, PIN_12>) {
         }
         last_press = now;
 
-        let message = match down.is_high() {
+        let pressed = down.is_high();
+        let message = match pressed {
             true => Event::Button(Buttons::Down(States::Pressed)),
             false => Event::Button(Buttons::Down(States::Released)),
         };
-        CONTROL_CHANNEL.send(message).await
+        CONTROL_CHANNEL.send(message).await;
+        if pressed && repeat_while_held(&mut down, Buttons::Down).await {
+            CONTROL_CHANNEL
+                .send(Event::Button(Buttons::Down(States::Released)))
+                .await
+        }
     }
 }
 
@@ -258,11 +293,17 @@ async fn button_left_task(_spawner: Spawner, mut left: Input<'static, PIN_11>) {
         }
         last_press = now;
 
-        let message = match left.is_high() {
+        let pressed = left.is_high();
+        let message = match pressed {
             true => Event::Button(Buttons::Left(States::Pressed)),
             false => Event::Button(Buttons::Left(States::Released)),
         };
-        CONTROL_CHANNEL.send(message).await
+        CONTROL_CHANNEL.send(message).await;
+        if pressed && repeat_while_held(&mut left, Buttons::Left).await {
+            CONTROL_CHANNEL
+                .send(Event::Button(Buttons::Left(States::Released)))
+                .await
+        }
     }
 }
 
@@ -278,11 +319,17 @@ async fn button_right_task(_spawner: Spawner, mut right: Input<'static, PIN_10>)
         }
         last_press = now;
 
-        let message = match right.is_high() {
+        let pressed = right.is_high();
+        let message = match pressed {
             true => Event::Button(Buttons::Right(States::Pressed)),
             false => Event::Button(Buttons::Right(States::Released)),
         };
-        CONTROL_CHANNEL.send(message).await
+        CONTROL_CHANNEL.send(message).await;
+        if pressed && repeat_while_held(&mut right, Buttons::Right).await {
+            CONTROL_CHANNEL
+                .send(Event::Button(Buttons::Right(States::Released)))
+                .await
+        }
     }
 }
 
@@ -307,13 +354,48 @@ async fn button_reset_btn_task(_spawner: Spawner, mut reset: Input<'static, PIN_
     }
 }
 
+/// Sends the press again while the button is held, as often as the settings say.
+/// Returns `true` once the button is released, `false` right away if repeat is off
+async fn repeat_while_held<T: Pin>(
+    input: &mut Input<'static, T>,
+    button: fn(States) -> Buttons,
+) -> bool {
+    let Some(interval) = settings::current().repeat_rate.interval_ms() else {
+        return false;
+    };
+
+    loop {
+        match select(
+            input.wait_for_low(),
+            Timer::after(Duration::from_millis(interval)),
+        )
+        .await
+        {
+            Either::First(_) => return true,
+            Either::Second(_) => {
+                CONTROL_CHANNEL
+                    .send(Event::Button(button(States::Pressed)))
+                    .await
+            }
+        }
+    }
+}
+
 #[embassy_executor::task]
 async fn timer_task(_spawner: Spawner) {
-    let mut ticker = Ticker::every(Duration::from_hz(10));
+    // Animation speed is the tick rate, it can change in the options
+    let mut hz = settings::current().animation_speed.tick_hz();
+    let mut ticker = Ticker::every(Duration::from_hz(hz));
     let mut tick: u128 = Default::default();
     loop {
         ticker.next().await;
         CONTROL_CHANNEL.send(Event::Tick(tick)).await;
         tick = tick.wrapping_add(1);
+
+        let current = settings::current().animation_speed.tick_hz();
+        if current != hz {
+            hz = current;
+            ticker = Ticker::every(Duration::from_hz(hz));
+        }
     }
 }
@@ -0,0 +1,150 @@
+//! Winning a level saves the progress and moves on, the level select lists it.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::save::{SaveData, SaveSlots};
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tiles::TileId;
+use koldun_level_format::{ItemKind, LevelData, Placement, HEIGHT, WIDTH};
+
+/// A level won by a single step right
+fn short_level() -> [u8; koldun_level_format::LEVEL_SIZE] {
+    let mut data = LevelData::new();
+    data.tiles = [[TileId::Empty as u8; WIDTH]; HEIGHT];
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 2, 1))
+        .unwrap();
+    data.encode()
+}
+
+struct Game {
+    sm: StateMachine<FramebufferDisplay, RamFlash>,
+    tick: u128,
+}
+
+impl Game {
+    fn new(count: usize, completed: usize) -> Self {
+        let mut flash = RamFlash::with_image(&short_level().repeat(count));
+        if completed > 0 {
+            let mut data = SaveData::default();
+            for index in 0..completed {
+                data.progress.complete(index, 5);
+            }
+            let mut slots = block_on(SaveSlots::load(&mut flash));
+            block_on(slots.store(&mut flash, &data)).unwrap();
+        }
+
+        let mut game = Game {
+            sm: StateMachine::new(FramebufferDisplay::new(), flash),
+            tick: 0,
+        };
+        block_on(game.sm.on_control(Event::Tick(0)));
+        game
+    }
+
+    fn level(&self) -> Option<usize> {
+        self.sm.level()
+    }
+
+    fn press(&mut self, button: fn(States) -> Buttons) {
+        block_on(self.sm.on_control(Event::Button(button(States::Pressed))));
+        block_on(self.sm.on_control(Event::Button(button(States::Released))));
+    }
+
+    /// Presses `button` and lets the move play out
+    fn step(&mut self, button: fn(States) -> Buttons) {
+        let level = self.level();
+        self.press(button);
+        for _ in 0..20 {
+            self.tick += 1;
+            block_on(self.sm.on_control(Event::Tick(self.tick)));
+            if self.level() != level {
+                return;
+            }
+        }
+    }
+
+    /// Steps right onto the exit
+    fn win(&mut self) {
+        let level = self.level();
+        self.step(Buttons::Right);
+        assert_ne!(self.level(), level, "level not won");
+    }
+
+    fn saved(&self) -> SaveData {
+        let mut flash = RamFlash::new();
+        flash.put(0, self.sm.flash().memory());
+        let slots = block_on(SaveSlots::load(&mut flash));
+        slots.data().cloned().unwrap()
+    }
+}
+
+#[test]
+fn win_advances_to_next_level() {
+    let mut game = Game::new(3, 0);
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), Some(0));
+
+    game.win();
+    assert_eq!(game.level(), Some(1));
+    let progress = game.saved().progress;
+    assert!(progress.is_completed(0));
+    assert!(progress.is_unlocked(1));
+    assert_eq!(progress.best_moves(0), Some(1));
+
+    game.win();
+    assert_eq!(game.level(), Some(2));
+    assert!(game.saved().progress.is_completed(1));
+}
+
+#[test]
+fn last_level_leads_to_level_select() {
+    let mut game = Game::new(2, 1);
+    // Continue starts the furthest level, the last one
+    game.press(Buttons::Up);
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), Some(1));
+
+    game.win();
+    assert_eq!(game.level(), None);
+
+    // The level select picks up at the level just won
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), Some(1));
+}
+
+#[test]
+fn level_select_enters_unlocked_levels_only() {
+    let mut game = Game::new(3, 1);
+    // New game, Continue, Levels
+    game.press(Buttons::Up);
+    game.press(Buttons::Up);
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), None);
+
+    // Starts at the continue level, the third one is locked
+    game.press(Buttons::Up);
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), None);
+
+    // Wraps round to the first one
+    game.press(Buttons::Up);
+    game.press(Buttons::Right);
+    assert_eq!(game.level(), Some(0));
+}
+
+#[test]
+fn best_moves_kept() {
+    let mut game = Game::new(2, 0);
+    game.press(Buttons::Right);
+
+    // A step off the path and back costs two moves
+    game.step(Buttons::Down);
+    game.step(Buttons::Up);
+    game.win();
+    assert_eq!(game.level(), Some(1));
+    assert_eq!(game.saved().progress.best_moves(0), Some(3));
+}
@@ -0,0 +1,197 @@
+//! Sprites are drawn over the layers under them, moving ones over both cells they
+//! straddle.
+
+use embassy_futures::block_on;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::{Point, Size};
+use embedded_graphics::primitives::Rectangle;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::colors::DUNGEON;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::state_mashine::states::level::compose::{
+    cells_area, touched_cells, Canvas, CANVAS_HEIGHT, CANVAS_WIDTH,
+};
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tile_cache::TileKey;
+use koldun::game::tiles::{TileId, MASK_BYTES};
+use koldun_level_format::{ItemKind, LevelData, Placement, HEIGHT, WIDTH};
+
+const FLOOR: TileId = TileId::Ground1;
+
+fn start(floor: TileId) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let mut data = LevelData::new();
+    data.tiles = [[floor as u8; WIDTH]; HEIGHT];
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 8, 6))
+        .unwrap();
+
+    let flash = RamFlash::with_image(&data.encode());
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.on_control(Event::Tick(0)));
+    block_on(sm.enter_level(0));
+    sm
+}
+
+fn pixels(tile: TileId) -> Vec<Rgb565> {
+    tile.themed(&DUNGEON)
+        .chunks_exact(2)
+        .map(|pixel| RawU16::new(u16::from_be_bytes([pixel[0], pixel[1]])).into())
+        .collect()
+}
+
+/// `over` moved by `shift` pixels on top of `under`, where its mask is set
+fn composed(under: TileId, over: TileId, shift: (i32, i32)) -> Vec<Rgb565> {
+    let (mut cell, over_pixels, mask) = (pixels(under), pixels(over), over.mask());
+    for (from, pixel) in over_pixels.iter().enumerate() {
+        let (x, y) = ((from % 32) as i32 + shift.0, (from / 32) as i32 + shift.1);
+        if (0..32).contains(&x) && (0..32).contains(&y) && mask[from / 8] >> (from % 8) & 1 == 1 {
+            cell[(y * 32 + x) as usize] = *pixel;
+        }
+    }
+    cell
+}
+
+fn cell(sm: &StateMachine<FramebufferDisplay, RamFlash>, x: usize, y: usize) -> Vec<Rgb565> {
+    (0..32 * 32)
+        .map(|i| sm.display().pixel(32 * x + i % 32, 32 * y + i / 32))
+        .collect()
+}
+
+#[test]
+fn masks() {
+    assert!(TileId::Ground1.mask().iter().all(|byte| *byte == 0xff));
+
+    // A sprite covers its set pixels only
+    let mask = TileId::WizardIdle1.mask();
+    assert_eq!(&mask[..], &TileId::WizardIdle1.bits()[..]);
+    assert!(mask.iter().any(|byte| *byte != 0xff));
+}
+
+#[test]
+fn floor_shows_around_sprite() {
+    let sm = start(FLOOR);
+    let expected = composed(FLOOR, TileId::WizardIdle1, (0, 0));
+    assert_eq!(cell(&sm, 1, 1), expected);
+
+    // Some of the floor is drawn where the wizard leaves it clear
+    let wizard = pixels(TileId::WizardIdle1);
+    let floor = pixels(FLOOR);
+    assert!((0..32 * 32).any(|i| expected[i] != wizard[i] && expected[i] == floor[i]));
+}
+
+#[test]
+fn moving_sprite_straddles_cells() {
+    let mut sm = start(FLOOR);
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Pressed))));
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Released))));
+    for tick in 1..=6 {
+        block_on(sm.on_control(Event::Tick(tick)));
+    }
+
+    // Partway into the next cell, over the floor of both
+    let (from, to) = (cell(&sm, 1, 1), cell(&sm, 2, 1));
+    let frames = [TileId::WizardRight1, TileId::WizardRight2];
+    let drawn = (-31..0).find_map(|shift| {
+        frames
+            .into_iter()
+            .find(|frame| composed(FLOOR, *frame, (shift, 0)) == to)
+            .map(|frame| (frame, shift))
+    });
+    let (frame, shift) = drawn.expect("wizard not drawn shifted into the next cell");
+    assert_eq!(from, composed(FLOOR, frame, (shift + 32, 0)));
+
+    // Both cells went out in one write
+    assert_eq!(sm.display().gram().window(), ((32, 95), (32, 63)));
+}
+
+#[test]
+fn touched_cells_off_the_grid() {
+    let cell = |x, y, width, height| Rectangle::new(Point::new(x, y), Size::new(width, height));
+    assert_eq!(touched_cells(Point::new(64, 32)), cell(2, 1, 1, 1));
+    assert_eq!(touched_cells(Point::new(70, 32)), cell(2, 1, 2, 1));
+    assert_eq!(touched_cells(Point::new(70, 20)), cell(2, 0, 2, 2));
+
+    // Cut to the grid at its edges
+    assert_eq!(touched_cells(Point::new(-5, -5)), cell(0, 0, 1, 1));
+    assert_eq!(
+        cells_area(cell(2, 0, 2, 2)),
+        Rectangle::new(Point::new(64, 0), Size::new(64, 64))
+    );
+}
+
+#[test]
+fn canvas_overlay() {
+    let area = Rectangle::new(Point::new(64, 32), Size::new(64, 32));
+    let mut canvas = Canvas::new();
+    canvas.start(area);
+    assert_eq!(canvas.data().len(), 64 * 32 * 2);
+
+    // A tile half over the right edge leaves its left half, where the mask is set
+    let floor = FLOOR.themed(&DUNGEON);
+    canvas.overlay(&floor, &FLOOR.mask(), Point::new(64, 32));
+    canvas.overlay(&floor, &FLOOR.mask(), Point::new(96, 32));
+    let wizard = TileId::WizardIdle1;
+    canvas.overlay(
+        &wizard.themed(&DUNGEON),
+        &wizard.mask(),
+        Point::new(112, 32),
+    );
+    let expected = composed(FLOOR, wizard, (16, 0));
+    for y in 0..32 {
+        for x in 0..32 {
+            let at = (y * 64 + 32 + x) * 2;
+            let pixel = RawU16::new(u16::from_be_bytes([
+                canvas.data()[at],
+                canvas.data()[at + 1],
+            ]));
+            assert_eq!(Rgb565::from(pixel), expected[y * 32 + x]);
+        }
+    }
+
+    // Expanded from the tiles' bits it comes out the same
+    let mut from_bits = Canvas::new();
+    from_bits.start(area);
+    for (tile, x) in [(FLOOR, 64), (FLOOR, 96), (wizard, 112)] {
+        let colors = TileKey::themed(tile, &DUNGEON);
+        from_bits.overlay_bits(
+            tile.bits(),
+            tile.depth(),
+            colors.colors(),
+            &tile.mask(),
+            Point::new(x, 32),
+        );
+    }
+    assert_eq!(from_bits.data(), canvas.data());
+
+    // Areas larger than the canvas are cut
+    canvas.start(Rectangle::new(Point::zero(), Size::new(480, 320)));
+    assert_eq!(
+        canvas.area().size,
+        Size::new(CANVAS_WIDTH as u32, CANVAS_HEIGHT as u32)
+    );
+}
+
+#[test]
+fn canvas_indexed_bits() {
+    // 4bpp rows indexing 0 to 15 twice, leftmost pixel in the lowest bits
+    let ramp = [0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe];
+    let bits: Vec<u8> = (0..64).flat_map(|_| ramp).collect();
+    let palette = DUNGEON.tiles;
+
+    let mut canvas = Canvas::new();
+    canvas.start(Rectangle::new(Point::zero(), Size::new(32, 32)));
+    canvas.overlay_bits(
+        &bits,
+        4,
+        palette.colors(16),
+        &[0xff; MASK_BYTES],
+        Point::zero(),
+    );
+    for (x, pixel) in canvas.data()[..32 * 2].chunks_exact(2).enumerate() {
+        let pixel = RawU16::new(u16::from_be_bytes([pixel[0], pixel[1]]));
+        assert_eq!(Rgb565::from(pixel), palette.0[x % 16]);
+    }
+}
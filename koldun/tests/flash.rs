@@ -0,0 +1,188 @@
+//! Host flashes keep the device's addressing: offsets relative to `ADDR_OFFSET`,
+//! word aligned reads, nothing past the end of the chip, erases and writes
+//! only in the storage region.
+
+use embassy_futures::block_on;
+use koldun::game::flash::{
+    FileFlash, Flash, FlashError, RamFlash, ADDR_OFFSET, ERASED, FLASH_SIZE, LEVELS_OFFSET,
+    SECTOR_SIZE, STORAGE_OFFSET, STORAGE_SIZE,
+};
+use koldun::game::state_mashine::states::level::load_level;
+use std::path::Path;
+
+const LEVELS: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/resources/levels/levels.bin");
+
+#[test]
+fn file_flash_loads_levels() {
+    let mut flash = FileFlash::open(LEVELS).unwrap();
+    let level = block_on(load_level(&mut flash, 0)).unwrap();
+    assert_eq!(level.name(), "Ruins");
+
+    // Nothing flashed after the first level
+    assert!(block_on(load_level(&mut flash, 1)).is_err());
+}
+
+#[test]
+fn image_at_addr_offset() {
+    let image = std::fs::read(LEVELS).unwrap();
+    let flash = FileFlash::open(LEVELS).unwrap();
+    assert_eq!(
+        &flash.memory()[ADDR_OFFSET + LEVELS_OFFSET..][..image.len()],
+        image.as_slice()
+    );
+    assert_eq!(flash.path(), Path::new(LEVELS));
+
+    // A full chip dump starts at address 0
+    let flash = FileFlash::open_at(LEVELS, 0).unwrap();
+    assert_eq!(&flash.memory()[..image.len()], image.as_slice());
+}
+
+#[test]
+fn words_are_little_endian() {
+    let mut flash = RamFlash::with_image(&[1, 2, 3, 4, 5]);
+    let buf = &mut [0u32; 2];
+    block_on(flash.load(0, buf));
+    assert_eq!(buf, &[0x0403_0201, 0xFFFF_FF05]);
+}
+
+#[test]
+fn erased_by_default() {
+    let mut flash = RamFlash::new();
+    let buf = &mut [0u32; 4];
+    block_on(flash.load(0x40, buf));
+    assert_eq!(buf, &[u32::MAX; 4]);
+}
+
+#[test]
+fn last_word() {
+    let mut flash = RamFlash::new();
+    let buf = &mut [0u32; 1];
+    block_on(flash.load(FLASH_SIZE - ADDR_OFFSET - 4, buf));
+}
+
+#[test]
+#[should_panic(expected = "OutOfBounds")]
+fn read_past_end() {
+    let mut flash = RamFlash::new();
+    let buf = &mut [0u32; 2];
+    block_on(flash.load(FLASH_SIZE - ADDR_OFFSET - 4, buf));
+}
+
+#[test]
+#[should_panic(expected = "Unaligned")]
+fn unaligned_read() {
+    let mut flash = RamFlash::new();
+    let buf = &mut [0u32; 1];
+    block_on(flash.load(2, buf));
+}
+
+#[test]
+fn load_tga_bytes() {
+    let image: Vec<u8> = (0..16).collect();
+    let mut flash = RamFlash::with_image(&image);
+    let data = block_on(flash.load_tga::<4, 13>(0));
+    assert_eq!(data, &image[..13]);
+}
+
+#[test]
+fn image_too_big() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("too_big.bin");
+    std::fs::write(&path, vec![0u8; FLASH_SIZE - ADDR_OFFSET + 1]).unwrap();
+    assert!(FileFlash::open(&path).is_err());
+}
+
+#[test]
+fn write_and_erase() {
+    let mut flash = RamFlash::new();
+    block_on(flash.write(STORAGE_OFFSET + 4, &[1, 2, 3, 4])).unwrap();
+    let buf = &mut [0u32; 2];
+    block_on(flash.load(STORAGE_OFFSET, buf));
+    assert_eq!(buf, &[u32::MAX, 0x0403_0201]);
+
+    block_on(flash.erase(STORAGE_OFFSET, SECTOR_SIZE)).unwrap();
+    block_on(flash.load(STORAGE_OFFSET, buf));
+    assert_eq!(buf, &[u32::MAX; 2]);
+}
+
+#[test]
+fn write_only_clears_bits() {
+    let mut flash = RamFlash::new();
+    block_on(flash.write(STORAGE_OFFSET, &[0b1100])).unwrap();
+    block_on(flash.write(STORAGE_OFFSET, &[0b1010])).unwrap();
+    assert_eq!(flash.memory()[ADDR_OFFSET + STORAGE_OFFSET], 0b1000);
+}
+
+#[test]
+fn assets_are_protected() {
+    let image = std::fs::read(LEVELS).unwrap();
+    let mut flash = RamFlash::with_image(&image);
+    assert_eq!(
+        block_on(flash.write(LEVELS_OFFSET, &[0])),
+        Err(FlashError::Protected(LEVELS_OFFSET))
+    );
+    assert_eq!(
+        block_on(flash.erase(STORAGE_OFFSET - SECTOR_SIZE, 2 * SECTOR_SIZE)),
+        Err(FlashError::Protected(STORAGE_OFFSET - SECTOR_SIZE))
+    );
+    assert_eq!(
+        &flash.memory()[ADDR_OFFSET..][..image.len()],
+        image.as_slice()
+    );
+}
+
+#[test]
+fn storage_bounds() {
+    let mut flash = RamFlash::new();
+    let last = STORAGE_OFFSET + STORAGE_SIZE - SECTOR_SIZE;
+    block_on(flash.erase(last, SECTOR_SIZE)).unwrap();
+    block_on(flash.write(STORAGE_OFFSET + STORAGE_SIZE - 1, &[0])).unwrap();
+    assert_eq!(
+        block_on(flash.write(STORAGE_OFFSET + STORAGE_SIZE - 1, &[0, 0])),
+        Err(FlashError::OutOfBounds(STORAGE_OFFSET + STORAGE_SIZE - 1))
+    );
+    assert_eq!(
+        block_on(flash.erase(last, 2 * SECTOR_SIZE)),
+        Err(FlashError::OutOfBounds(last))
+    );
+}
+
+#[test]
+fn unaligned_erase() {
+    let mut flash = RamFlash::new();
+    assert_eq!(
+        block_on(flash.erase(STORAGE_OFFSET + 4, SECTOR_SIZE)),
+        Err(FlashError::Unaligned(STORAGE_OFFSET + 4))
+    );
+    assert_eq!(
+        block_on(flash.erase(STORAGE_OFFSET, 4)),
+        Err(FlashError::Unaligned(STORAGE_OFFSET))
+    );
+}
+
+#[test]
+fn file_flash_storage() {
+    let path = Path::new(env!("CARGO_TARGET_TMPDIR")).join("storage.bin");
+    let _ = std::fs::remove_file(&path);
+
+    let mut flash = FileFlash::open(LEVELS)
+        .unwrap()
+        .with_storage(&path)
+        .unwrap();
+    assert_eq!(flash.storage_path(), Some(path.as_path()));
+    block_on(flash.write(STORAGE_OFFSET + 8, &[0x42])).unwrap();
+    let storage = std::fs::read(&path).unwrap();
+    assert_eq!(storage.len(), STORAGE_SIZE);
+    assert_eq!(storage[8], 0x42);
+    assert_eq!(storage[0], ERASED);
+
+    // The image itself is left alone
+    let reopened = FileFlash::open(LEVELS)
+        .unwrap()
+        .with_storage(&path)
+        .unwrap();
+    assert_eq!(reopened.memory()[ADDR_OFFSET + STORAGE_OFFSET + 8], 0x42);
+    assert_eq!(
+        FileFlash::open(LEVELS).unwrap().memory()[ADDR_OFFSET + STORAGE_OFFSET + 8],
+        ERASED
+    );
+}
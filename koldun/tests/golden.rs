@@ -0,0 +1,306 @@
+//! Golden image tests for level rendering.
+//!
+//! Every case drives a level on a `FramebufferDisplay` and compares the frame
+//! with `tests/golden/<case>.png`. After an intended change in rendering run
+//! the tests with `KOLDUN_BLESS=1` to rewrite the references, and check the
+//! new images before committing them.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::{FramebufferDisplay, HEIGHT, WIDTH};
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::settings::Theme;
+use koldun::game::state_mashine::states::level::Level;
+use koldun::game::state_mashine::states::State;
+use koldun::game::tile_cache::{TileCache, DEFAULT_BUDGET};
+use koldun::game::tiles::TileId;
+use koldun_level_format::{
+    ItemKind, LevelData, Placement, HEIGHT as LEVEL_HEIGHT, WIDTH as LEVEL_WIDTH,
+};
+use std::env;
+use std::fmt::Write;
+use std::fs::{self, File};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+const LEVELS: &[u8] = include_bytes!("../resources/levels/levels.bin");
+const MAX_REPORTED_PIXELS: usize = 8;
+
+struct Case {
+    level: Level,
+    display: FramebufferDisplay,
+    flash: RamFlash,
+    tiles: TileCache,
+    tick: u128,
+}
+
+impl Case {
+    fn new(index: usize) -> Self {
+        Case::with_image(LEVELS, index)
+    }
+
+    /// Level `index` of a levels image other than the built one
+    fn with_image(image: &[u8], index: usize) -> Self {
+        let mut case = Case {
+            level: Level::new(index),
+            display: FramebufferDisplay::new(),
+            flash: RamFlash::with_image(image),
+            tiles: TileCache::new(DEFAULT_BUDGET),
+            tick: 0,
+        };
+        block_on(State::<FramebufferDisplay, RamFlash>::on_init(
+            &mut case.level,
+            &mut case.display,
+            &mut case.flash,
+            &mut case.tiles,
+        ));
+        case
+    }
+
+    fn event(&mut self, event: Event) {
+        let next = block_on(State::<FramebufferDisplay, RamFlash>::on_event(
+            &mut self.level,
+            event,
+            &mut self.display,
+            &mut self.flash,
+            &mut self.tiles,
+        ));
+        assert!(next.is_none(), "level unexpectedly left");
+    }
+
+    fn ticks(&mut self, count: usize) {
+        for _ in 0..count {
+            self.tick += 1;
+            self.event(Event::Tick(self.tick));
+        }
+    }
+
+    fn press(&mut self, button: fn(States) -> Buttons) {
+        self.event(Event::Button(button(States::Pressed)));
+        self.event(Event::Button(button(States::Released)));
+    }
+
+    fn check(&self, name: &str) {
+        check_golden(name, &self.display);
+    }
+
+    fn frame(&self) -> Vec<u16> {
+        self.display.raw().to_vec()
+    }
+}
+
+#[test]
+fn ruins_init() {
+    let case = Case::new(0);
+    case.check("ruins_init");
+}
+
+#[test]
+fn ruins_idle() {
+    let mut case = Case::new(0);
+    case.ticks(10);
+    case.check("ruins_idle");
+}
+
+#[test]
+fn ruins_move_right() {
+    let mut case = Case::new(0);
+    case.ticks(1);
+    let start = case.frame();
+    case.press(Buttons::Right);
+
+    // Halfway through, the wizard is drawn shifted between the two cells
+    case.ticks(5);
+    case.check("ruins_move_right_half");
+    let half = case.frame();
+
+    case.ticks(10);
+    case.check("ruins_move_right_done");
+    let done = case.frame();
+
+    assert!(half != start, "wizard didn't start moving");
+    assert!(half != done, "wizard didn't stop halfway");
+    assert!(done != start, "wizard didn't move");
+}
+
+#[test]
+fn ruins_move_down() {
+    let mut case = Case::new(0);
+    case.ticks(1);
+    case.press(Buttons::Down);
+
+    case.ticks(3);
+    case.check("ruins_move_down_third");
+
+    case.ticks(12);
+    case.check("ruins_move_down_done");
+}
+
+#[test]
+fn ruins_blocked() {
+    // A wall is left of the wizard, pressing into it changes nothing
+    let mut case = Case::new(0);
+    case.ticks(1);
+    case.press(Buttons::Left);
+    case.ticks(15);
+    case.check("ruins_blocked");
+
+    let mut idle = Case::new(0);
+    idle.ticks(16);
+    assert!(case.frame() == idle.frame(), "wizard moved into the wall");
+}
+
+#[test]
+fn indexed_tiles() {
+    // Cobbles under pillars, both from the 4bpp sheet, in the shades of the ice theme
+    let mut data = LevelData::new();
+    data.tiles = [[TileId::Cobbles as u8; LEVEL_WIDTH]; LEVEL_HEIGHT];
+    for (x, y) in [(2, 2), (5, 2), (2, 5), (5, 5), (9, 3), (12, 6)] {
+        data.tiles[y][x] = TileId::Pillar as u8;
+    }
+    data.theme = Some(Theme::Ice as u8);
+    data.push_item(Placement::new(ItemKind::Wizard, 3, 3))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 11, 8))
+        .unwrap();
+
+    let case = Case::with_image(&data.encode(), 0);
+    case.check("indexed_tiles");
+}
+
+fn golden_path(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+fn check_golden(name: &str, display: &FramebufferDisplay) {
+    let path = golden_path(name);
+    if env::var_os("KOLDUN_BLESS").is_some() {
+        display.save_png(&path).unwrap();
+        return;
+    }
+
+    let expected = match read_png(&path) {
+        Ok(expected) => expected,
+        Err(err) => panic!(
+            "{}: {}, run with KOLDUN_BLESS=1 to create it",
+            path.display(),
+            err
+        ),
+    };
+    let actual = display.to_rgb888();
+    if expected == actual {
+        return;
+    }
+
+    let out = Path::new(env!("CARGO_TARGET_TMPDIR")).join("golden");
+    fs::create_dir_all(&out).unwrap();
+    let actual_path = out.join(format!("{}.actual.png", name));
+    let diff_path = out.join(format!("{}.diff.png", name));
+    display.save_png(&actual_path).unwrap();
+    write_png(&diff_path, &diff_image(&expected, &actual));
+
+    panic!(
+        "{} differs from {}\n{}actual: {}\ndiff:   {}",
+        name,
+        path.display(),
+        diff_report(&expected, &actual),
+        actual_path.display(),
+        diff_path.display(),
+    );
+}
+
+fn diff_report(expected: &[u8], actual: &[u8]) -> String {
+    let mut report = String::new();
+    if expected.len() != actual.len() {
+        writeln!(
+            report,
+            "reference is {} bytes, expected {}x{} RGB",
+            expected.len(),
+            WIDTH,
+            HEIGHT
+        )
+        .unwrap();
+        return report;
+    }
+
+    let differing: Vec<(usize, usize)> = (0..WIDTH * HEIGHT)
+        .filter(|i| expected[i * 3..i * 3 + 3] != actual[i * 3..i * 3 + 3])
+        .map(|i| (i % WIDTH, i / WIDTH))
+        .collect();
+
+    let (min_x, max_x) = (
+        differing.iter().map(|p| p.0).min().unwrap(),
+        differing.iter().map(|p| p.0).max().unwrap(),
+    );
+    let (min_y, max_y) = (
+        differing.iter().map(|p| p.1).min().unwrap(),
+        differing.iter().map(|p| p.1).max().unwrap(),
+    );
+    writeln!(
+        report,
+        "{} pixels differ in ({}, {})..=({}, {}), cells ({}, {})..=({}, {})",
+        differing.len(),
+        min_x,
+        min_y,
+        max_x,
+        max_y,
+        min_x / 32,
+        min_y / 32,
+        max_x / 32,
+        max_y / 32,
+    )
+    .unwrap();
+
+    for (x, y) in differing.iter().take(MAX_REPORTED_PIXELS) {
+        let i = (y * WIDTH + x) * 3;
+        writeln!(
+            report,
+            "  ({}, {}): expected {:02x?}, got {:02x?}",
+            x,
+            y,
+            &expected[i..i + 3],
+            &actual[i..i + 3]
+        )
+        .unwrap();
+    }
+    if differing.len() > MAX_REPORTED_PIXELS {
+        writeln!(report, "  ...").unwrap();
+    }
+    report
+}
+
+/// Differing pixels in red over a dimmed copy of the actual frame
+fn diff_image(expected: &[u8], actual: &[u8]) -> Vec<u8> {
+    actual
+        .chunks(3)
+        .enumerate()
+        .flat_map(
+            |(i, pixel)| match expected.get(i * 3..i * 3 + 3) == Some(pixel) {
+                true => [pixel[0] / 4, pixel[1] / 4, pixel[2] / 4],
+                false => [0xFF, 0, 0],
+            },
+        )
+        .collect()
+}
+
+fn read_png(path: &Path) -> Result<Vec<u8>, png::DecodingError> {
+    let mut reader = png::Decoder::new(File::open(path)?).read_info()?;
+    let mut data = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut data)?;
+    data.truncate(info.buffer_size());
+    Ok(data)
+}
+
+fn write_png(path: &Path, rgb: &[u8]) {
+    let file = BufWriter::new(File::create(path).unwrap());
+    let mut encoder = png::Encoder::new(file, WIDTH as u32, HEIGHT as u32);
+    encoder.set_color(png::ColorType::Rgb);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .unwrap()
+        .write_image_data(rgb)
+        .unwrap();
+}
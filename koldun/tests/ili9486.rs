@@ -0,0 +1,320 @@
+//! `Ili9486` driven through `MockParallel`, checking the command stream and the
+//! frame memory it leaves behind.
+
+use embassy_futures::block_on;
+use embedded_graphics::pixelcolor::raw::RawU16;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::*;
+use embedded_graphics::primitives::Rectangle;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::colors::TilePalette;
+use koldun::game::tiles::TileId;
+use koldun::ili9486::gram::{Gram, HEIGHT, WIDTH};
+use koldun::ili9486::mock::MockParallel;
+use koldun::ili9486::{Command, Display, Ili9486, Order, PixelFormat, STREAM_ROWS};
+
+fn display() -> Ili9486<MockParallel> {
+    Ili9486::new(MockParallel::new())
+}
+
+fn commands(display: &Ili9486<MockParallel>) -> Vec<Command> {
+    display
+        .interface()
+        .commands()
+        .iter()
+        .map(|(command, _)| *command)
+        .collect()
+}
+
+fn order(reverse: bool) -> Order {
+    match reverse {
+        true => Order::Reverse,
+        false => Order::Forward,
+    }
+}
+
+/// Pixels of `color` in the frame, and whether all of them lie inside `area`
+fn painted(gram: &Gram, color: Rgb565, area: &Rectangle) -> (usize, bool) {
+    let mut count = 0;
+    let mut inside = true;
+    for y in 0..HEIGHT {
+        for x in 0..WIDTH {
+            if gram.pixel(x, y) == color {
+                count += 1;
+                inside &= area.contains(Point::new(x as i32, y as i32));
+            }
+        }
+    }
+    (count, inside)
+}
+
+#[test]
+fn address_window() {
+    let mut display = display();
+    let area = Rectangle::new(Point::new(300, 260), Size::new(3, 2));
+    let data: Vec<u8> = (0..6u16).flat_map(|i| (i + 1).to_be_bytes()).collect();
+    block_on(display.draw_data(area, &data));
+
+    let commands = display.interface().commands();
+    assert_eq!(commands.len(), 3);
+    assert_eq!(
+        commands[0],
+        (Command::ColumnAddressSet, vec![0x01, 0x2c, 0x01, 0x2e])
+    );
+    assert_eq!(
+        commands[1],
+        (Command::PageAddressSet, vec![0x01, 0x04, 0x01, 0x05])
+    );
+    assert_eq!(commands[2], (Command::MemoryWrite, data.clone()));
+
+    let gram = display.interface().gram();
+    assert_eq!(gram.window(), ((300, 302), (260, 261)));
+    for (i, (x, y)) in [
+        (300, 260),
+        (301, 260),
+        (302, 260),
+        (300, 261),
+        (301, 261),
+        (302, 261),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        assert_eq!(gram.pixel(x, y), Rgb565::from(RawU16::new(i as u16 + 1)));
+    }
+    assert_eq!(gram.raw().iter().filter(|raw| **raw != 0).count(), 6);
+}
+
+#[test]
+fn memory_write_wraps_in_window() {
+    let mut display = display();
+    let area = Rectangle::new(Point::new(0, 0), Size::new(2, 2));
+    let data: Vec<u8> = (1..=6u16).flat_map(|i| i.to_be_bytes()).collect();
+    block_on(display.draw_data(area, &data));
+
+    let gram = display.interface().gram();
+    assert_eq!(gram.pixel(0, 0), Rgb565::from(RawU16::new(5)));
+    assert_eq!(gram.pixel(1, 0), Rgb565::from(RawU16::new(6)));
+    assert_eq!(gram.pixel(0, 1), Rgb565::from(RawU16::new(3)));
+    assert_eq!(gram.pixel(1, 1), Rgb565::from(RawU16::new(4)));
+    assert_eq!(gram.pixel(2, 0), Rgb565::BLACK);
+}
+
+#[test]
+fn draw_tile() {
+    let mut display = display();
+    let color = Rgb565::CSS_ORANGE;
+    let data: Vec<u8> = (0..32 * 32)
+        .flat_map(|_| RawU16::from(color).into_inner().to_be_bytes())
+        .collect();
+    block_on(display.draw_tile(Point::new(64, 32), &data));
+
+    let gram = display.interface().gram();
+    let area = Rectangle::new(Point::new(64, 32), Size::new(32, 32));
+    assert_eq!(painted(&gram, color, &area), (32 * 32, true));
+}
+
+#[test]
+fn draw_tile_bits() {
+    let bits = TileId::Tree.bits();
+    let colors = [Rgb565::CSS_NAVY, Rgb565::CSS_ORANGE];
+    let mut streamed = display();
+    block_on(streamed.draw_tile_bits(Point::new(64, 32), bits, 1, &colors));
+
+    // A window, then the tile a few rows at a time
+    let writes: Vec<Command> = commands(&streamed).into_iter().skip(2).collect();
+    assert_eq!(writes[0], Command::MemoryWrite);
+    assert_eq!(writes.len(), 32 / STREAM_ROWS);
+    assert!(writes[1..]
+        .iter()
+        .all(|command| *command == Command::MemoryWriteContinue));
+
+    let mut rendered = display();
+    block_on(rendered.draw_tile(Point::new(64, 32), &TileId::Tree.render(&colors)));
+    assert!(streamed.interface().gram().raw() == rendered.interface().gram().raw());
+}
+
+#[test]
+fn indexed_tile_bits() {
+    let colors: Vec<Rgb565> = (0..16).map(|i| Rgb565::new(i, 2 * i, 31 - i)).collect();
+    for depth in [2, 4] {
+        // Pixel `n` of the tile takes colour `n % count`, packed lowest bits first
+        let count = 1 << depth;
+        let bits: Vec<u8> = (0..32 * 32 / (8 / depth))
+            .map(|byte| {
+                (0..8 / depth).fold(0, |packed, i| {
+                    let value = (byte * (8 / depth) + i) % count;
+                    packed | (value << (i * depth)) as u8
+                })
+            })
+            .collect();
+
+        let mut display = display();
+        block_on(display.draw_tile_bits(Point::new(32, 64), &bits, depth, &colors[..count]));
+        assert_eq!(commands(&display).len(), 2 + 32 / STREAM_ROWS);
+
+        let gram = display.interface().gram();
+        for n in 0..32 * 32 {
+            let (x, y) = (32 + n % 32, 64 + n / 32);
+            assert_eq!(gram.pixel(x, y), colors[n % count], "{depth}bpp pixel {n}");
+        }
+    }
+}
+
+/// A 2bpp tile packed by hand, leftmost pixel in the lowest bits: even rows index
+/// 0, 1, 2, 3 over and over, odd rows 3, 2, 1, 0
+fn two_bpp_fixture() -> Vec<u8> {
+    (0..32)
+        .flat_map(|y| [[0xe4; 8], [0x1b; 8]][y % 2])
+        .collect()
+}
+
+/// A 4bpp tile packed by hand: the top half indexes 0 to 15 twice a row, the bottom
+/// half is all 15
+fn four_bpp_fixture() -> Vec<u8> {
+    let ramp = [0x10, 0x32, 0x54, 0x76, 0x98, 0xba, 0xdc, 0xfe];
+    let top = (0..16).flat_map(|_| ramp.into_iter().chain(ramp));
+    top.chain([0xff; 16 * 16]).collect()
+}
+
+#[test]
+fn indexed_fixture() {
+    // Every colour differs, a pixel tells the index it was drawn with
+    let palette = TilePalette::ramp((0, 0, 31), (31, 63, 0));
+    assert!((1..16).all(|i| !palette.0[..i].contains(&palette.0[i])));
+
+    let origin = Point::new(96, 32);
+    let fixtures = [
+        (two_bpp_fixture(), 2, [0, 1, 2, 3, 3], [3, 2, 1, 0, 0]),
+        (four_bpp_fixture(), 4, [0, 1, 2, 3, 15], [15; 5]),
+    ];
+    for (bits, depth, top, bottom) in fixtures {
+        let colors = palette.colors(1 << depth);
+        let mut display = display();
+        block_on(display.draw_tile_bits(origin, &bits, depth, colors));
+        let mut framebuffer = FramebufferDisplay::new();
+        block_on(framebuffer.draw_tile_bits(origin, &bits, depth, colors));
+
+        // Columns 0, 1, 2, 3 and 15 of the first and the last row
+        for (row, indices) in [(0, top), (31, bottom)] {
+            for (column, index) in [0, 1, 2, 3, 15].into_iter().zip(indices) {
+                let (x, y) = (96 + column, 32 + row);
+                let expected = palette.0[index];
+                assert_eq!(display.interface().gram().pixel(x, y), expected);
+                assert_eq!(framebuffer.pixel(x, y), expected);
+            }
+        }
+        assert!(display.interface().gram().raw() == framebuffer.raw());
+    }
+}
+
+#[test]
+fn memory_access_control_bits() {
+    // Reverse flags in argument order: row, column, exchange, vertical, horizontal, color
+    let cases = [
+        ([false, false, false, false, false, false], 0x00),
+        ([true, false, false, false, false, false], 1 << 7),
+        ([false, true, false, false, false, false], 1 << 6),
+        ([false, false, true, false, false, false], 1 << 5),
+        ([false, false, false, true, false, false], 1 << 4),
+        ([false, false, false, false, true, false], 1 << 2),
+        ([false, false, false, false, false, true], 1 << 3),
+        // What `main` configures
+        ([true, true, true, false, false, true], 0b1110_1000),
+    ];
+
+    for (flags, expected) in cases {
+        let [row, column, exchange, vert, hor, color] = flags.map(order);
+        let mut display = display();
+        block_on(display.memory_access_control(row, column, exchange, vert, hor, color));
+        assert_eq!(
+            display.interface().commands(),
+            &[(Command::MemoryAccessControl, vec![expected])]
+        );
+        assert_eq!(display.interface().gram().madctl(), expected);
+    }
+}
+
+#[test]
+fn init_sequence() {
+    let mut display = display();
+    block_on(async {
+        display.set_pixel_format(PixelFormat::Bit16).await;
+        display.sleep_out().await;
+        display.display_on().await;
+    });
+
+    assert_eq!(
+        commands(&display),
+        [
+            Command::InterfacePixelFormat,
+            Command::SleepOut,
+            Command::DisplayOn
+        ]
+    );
+    let gram = display.interface().gram();
+    assert_eq!(gram.pixel_format(), PixelFormat::Bit16 as u8);
+    assert!(gram.is_on());
+}
+
+#[test]
+fn brightness() {
+    let mut display = display();
+    block_on(display.set_brightness(0x80));
+
+    assert_eq!(
+        display.interface().commands(),
+        [
+            (Command::WriteCTRLDisplayValue, vec![0b0010_0100]),
+            (Command::WriteDisplayBrightnessValue, vec![0x80]),
+        ]
+    );
+    assert_eq!(display.interface().gram().brightness(), 0x80);
+}
+
+#[test]
+fn fill_solid_tiles() {
+    let mut display = display();
+    let color = Rgb565::CSS_DARK_RED;
+    // Straddles tile borders on both axes
+    let area = Rectangle::new(Point::new(20, 50), Size::new(50, 40));
+    display.fill_solid(&area, color).unwrap();
+
+    // One window and write per 32x32 tile the area touches: columns 0..=2, rows 1..=2
+    let writes = commands(&display)
+        .into_iter()
+        .filter(|command| *command == Command::MemoryWrite)
+        .count();
+    assert_eq!(writes, 3 * 2);
+
+    let gram = display.interface().gram();
+    assert_eq!(painted(&gram, color, &area), (50 * 40, true));
+}
+
+#[test]
+fn clear_covers_screen() {
+    let mut display = display();
+    display.clear(Rgb565::CSS_NAVY).unwrap();
+
+    let writes = commands(&display)
+        .into_iter()
+        .filter(|command| *command == Command::MemoryWrite)
+        .count();
+    assert_eq!(writes, (WIDTH / 32) * (HEIGHT / 32));
+
+    let gram = display.interface().gram();
+    assert!(gram
+        .raw()
+        .iter()
+        .all(|raw| *raw == RawU16::from(Rgb565::CSS_NAVY).into_inner()));
+}
+
+#[test]
+fn draw_solid_area_tiles() {
+    let mut display = display();
+    let area = Rectangle::new(Point::new(32, 64), Size::new(64, 96));
+    block_on(display.draw_solid_area(area, Rgb565::CSS_TEAL));
+
+    let gram = display.interface().gram();
+    assert_eq!(painted(&gram, Rgb565::CSS_TEAL, &area), (64 * 96, true));
+}
@@ -0,0 +1,96 @@
+//! Start menu entries, driven through the `StateMachine`.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::save::{SaveData, SaveSlots};
+use koldun::game::settings::RepeatRate;
+use koldun::game::state_mashine::StateMachine;
+use koldun_level_format::LEVEL_SIZE;
+
+const LEVELS: &[u8] = include_bytes!("../resources/levels/levels.bin");
+
+/// Flash with `count` copies of the first level and a save with `completed` levels won
+fn flash(count: usize, completed: Option<usize>) -> RamFlash {
+    let mut flash = RamFlash::with_image(&LEVELS[..LEVEL_SIZE].repeat(count));
+    if let Some(completed) = completed {
+        let mut data = SaveData::default();
+        for index in 0..completed {
+            data.progress.complete(index, 10);
+        }
+        let mut slots = block_on(SaveSlots::load(&mut flash));
+        block_on(slots.store(&mut flash, &data)).unwrap();
+    }
+    flash
+}
+
+fn start(flash: RamFlash) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.on_control(Event::Tick(0)));
+    assert_eq!(sm.level(), None);
+    sm
+}
+
+fn press(sm: &mut StateMachine<FramebufferDisplay, RamFlash>, button: fn(States) -> Buttons) {
+    block_on(sm.on_control(Event::Button(button(States::Pressed))));
+    block_on(sm.on_control(Event::Button(button(States::Released))));
+}
+
+#[test]
+fn new_game() {
+    let mut sm = start(flash(3, Some(2)));
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(0));
+}
+
+#[test]
+fn continue_furthest_level() {
+    let mut sm = start(flash(3, Some(2)));
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(2));
+}
+
+#[test]
+fn continue_skips_missing_levels() {
+    let mut sm = start(flash(2, Some(5)));
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(1));
+}
+
+#[test]
+fn continue_disabled_without_progress() {
+    // Saving the options alone makes a save with no level won
+    let mut flash = flash(3, None);
+    let mut data = SaveData::default();
+    data.settings.repeat_rate = RepeatRate::Slow;
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    block_on(slots.store(&mut flash, &data)).unwrap();
+
+    let mut sm = start(flash);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(0));
+}
+
+#[test]
+fn continue_disabled_without_save() {
+    // Continue is skipped both ways, three steps go round to New game
+    let mut sm = start(flash(3, None));
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(0));
+
+    let mut sm = start(flash(3, None));
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(0));
+}
@@ -0,0 +1,106 @@
+//! Settings are applied at boot, changed on the options screen and saved.
+//!
+//! The settings in effect are global, so everything touching them runs in
+//! one test.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::save::{SaveData, SaveSlots};
+use koldun::game::settings::{
+    self, AnimationSpeed, Brightness, Language, RepeatRate, Settings, Theme, SETTINGS_SIZE,
+};
+use koldun::game::state_mashine::StateMachine;
+
+const LEVELS: &[u8] = include_bytes!("../resources/levels/levels.bin");
+
+fn press(sm: &mut StateMachine<FramebufferDisplay, RamFlash>, button: fn(States) -> Buttons) {
+    block_on(sm.on_control(Event::Button(button(States::Pressed))));
+    block_on(sm.on_control(Event::Button(button(States::Released))));
+}
+
+#[test]
+fn options() {
+    let saved = Settings {
+        theme: Theme::Forest,
+        animation_speed: AnimationSpeed::Fast,
+        repeat_rate: RepeatRate::Slow,
+        brightness: Brightness::Medium,
+        language: Language::Russian,
+    };
+    let mut flash = RamFlash::with_image(LEVELS);
+    let mut data = SaveData::default();
+    data.progress.complete(0, 12);
+    data.settings = saved;
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    block_on(slots.store(&mut flash, &data)).unwrap();
+
+    // Applied before the start menu shows up
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.on_control(Event::Tick(0)));
+    assert_eq!(settings::current(), saved);
+    assert_eq!(sm.display().brightness(), Brightness::Medium.value());
+
+    // Options is the fourth entry, the theme is its first row
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), None);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(settings::current().theme, Theme::Ice);
+
+    // Brightness takes effect at once
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Up);
+    press(&mut sm, Buttons::Right);
+    assert_eq!(settings::current().brightness, Brightness::High);
+    assert_eq!(sm.display().brightness(), Brightness::High.value());
+
+    // Back, wrapping past the first row, saves next to the progress
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Down);
+    press(&mut sm, Buttons::Right);
+
+    let mut flash = RamFlash::new();
+    flash.put(0, sm.flash().memory());
+    let slots = block_on(SaveSlots::load(&mut flash));
+    let data = slots.data().unwrap();
+    assert_eq!(
+        data.settings,
+        Settings {
+            theme: Theme::Ice,
+            brightness: Brightness::High,
+            ..saved
+        }
+    );
+    assert!(data.progress.is_completed(0));
+
+    // and the start menu is back: New game starts the first level
+    press(&mut sm, Buttons::Right);
+    assert_eq!(sm.level(), Some(0));
+
+    settings::set_current(&Settings::DEFAULT);
+}
+
+#[test]
+fn unknown_values_fall_back() {
+    let data = [Theme::Ice as u8, 7, RepeatRate::Fast as u8, 200, 1, 0, 0, 0];
+    let decoded = Settings::decode(&data);
+    assert_eq!(
+        decoded,
+        Settings {
+            theme: Theme::Ice,
+            repeat_rate: RepeatRate::Fast,
+            language: Language::Russian,
+            ..Settings::DEFAULT
+        }
+    );
+
+    let encoded: [u8; SETTINGS_SIZE] = decoded.encode();
+    assert_eq!(Settings::decode(&encoded), decoded);
+}
@@ -0,0 +1,252 @@
+//! Recordings survive an encode and decode, broken or stale ones are rejected.
+
+use core::future::ready;
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::{RamFlash, ADDR_OFFSET, REPLAY_OFFSET};
+use koldun::game::replay::{Recorder, Replay, ReplayError, HEADER_SIZE, MAGIC, VERSION};
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tiles::TileId;
+use koldun_level_format::{ItemKind, LevelData, Placement, HEIGHT, WIDTH};
+
+fn level(floor: TileId) -> LevelData {
+    let mut data = LevelData::new();
+    data.tiles = [[floor as u8; WIDTH]; HEIGHT];
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 8, 6))
+        .unwrap();
+    data
+}
+
+fn machine(data: &LevelData) -> StateMachine<FramebufferDisplay, RamFlash> {
+    StateMachine::new(
+        FramebufferDisplay::new(),
+        RamFlash::with_image(&data.encode()),
+    )
+}
+
+fn press(button: fn(States) -> Buttons) -> [Event; 2] {
+    [
+        Event::Button(button(States::Pressed)),
+        Event::Button(button(States::Released)),
+    ]
+}
+
+/// Events as text, they don't compare
+fn describe(events: impl IntoIterator<Item = Event>) -> Vec<String> {
+    let state = |state: &States| match state {
+        States::Pressed => "pressed",
+        States::Released => "released",
+    };
+    events
+        .into_iter()
+        .map(|event| match event {
+            Event::Tick(tick) => format!("tick {}", tick),
+            Event::Button(button) => match &button {
+                Buttons::Up(s) => format!("up {}", state(s)),
+                Buttons::Down(s) => format!("down {}", state(s)),
+                Buttons::Left(s) => format!("left {}", state(s)),
+                Buttons::Right(s) => format!("right {}", state(s)),
+                Buttons::Reset(s) => format!("reset {}", state(s)),
+            },
+        })
+        .collect()
+}
+
+fn events() -> Vec<Event> {
+    let mut events = vec![Event::Tick(40), Event::Tick(41)];
+    events.extend(press(Buttons::Right));
+    events.extend((42..=49).map(Event::Tick));
+    events.extend(press(Buttons::Down));
+    events.push(Event::Button(Buttons::Reset(States::Pressed)));
+    events.extend((50..=52).map(Event::Tick));
+    events
+}
+
+fn recording() -> Vec<u8> {
+    let mut recorder = Recorder::new();
+    assert!(recorder.enter(Some(3)));
+    recorder.set_level_hash(0xdead_beef);
+    for event in events() {
+        recorder.record(&event);
+    }
+    recorder.encode().unwrap()
+}
+
+#[test]
+fn round_trip() {
+    let data = recording();
+    assert_eq!(data[0..4], MAGIC);
+    assert_eq!(data[4], VERSION);
+
+    let replay = Replay::decode(&data).unwrap();
+    assert_eq!(replay.level(), 3);
+    assert_eq!(replay.level_hash(), 0xdead_beef);
+    assert_eq!(describe(replay.events()), describe(events()));
+}
+
+#[test]
+fn recording_follows_levels() {
+    let mut recorder = Recorder::new();
+    assert_eq!(recorder.encode(), None);
+    assert!(!recorder.enter(None));
+    assert!(recorder.enter(Some(0)));
+    assert!(!recorder.enter(Some(0)));
+    assert!(recorder.enter(Some(1)));
+
+    // Back in the same level after the menus is a new recording
+    assert!(!recorder.enter(None));
+    assert!(recorder.enter(Some(1)));
+}
+
+#[test]
+fn rejected() {
+    let data = recording();
+
+    let mut bad = data.clone();
+    bad[0] = b'X';
+    assert!(matches!(Replay::decode(&bad), Err(ReplayError::BadMagic)));
+
+    let mut bad = data.clone();
+    bad[4] = VERSION + 1;
+    assert!(matches!(
+        Replay::decode(&bad),
+        Err(ReplayError::UnsupportedVersion(version)) if version == VERSION + 1
+    ));
+
+    assert!(matches!(
+        Replay::decode(&data[..HEADER_SIZE - 1]),
+        Err(ReplayError::TooShort(_))
+    ));
+    assert!(matches!(
+        Replay::decode(&data[..data.len() - 1]),
+        Err(ReplayError::TooShort(_))
+    ));
+
+    // A button code past the last one
+    let mut bad = data.clone();
+    bad[HEADER_SIZE + 1] = 0x7f;
+    assert!(matches!(
+        Replay::decode(&bad),
+        Err(ReplayError::BadEntry(offset)) if offset == HEADER_SIZE + 1
+    ));
+}
+
+/// Wizard moves on level 0, as a player would make them
+fn play() -> Vec<Event> {
+    let mut events = Vec::new();
+    for (button, ticks) in [
+        (Buttons::Right as fn(States) -> Buttons, 1..=8),
+        (Buttons::Down, 9..=16),
+    ] {
+        events.extend(press(button));
+        events.extend(ticks.map(Event::Tick));
+    }
+    events
+}
+
+/// Records `play` on level 0 of `data`, the way the simulator does
+fn record_on(data: &LevelData) -> Vec<u8> {
+    let mut sm = machine(data);
+    sm.record();
+    block_on(sm.on_control(Event::Tick(0)));
+    block_on(sm.enter_level(0));
+    for event in play() {
+        block_on(sm.on_control(event));
+    }
+    sm.recorder().unwrap().encode().unwrap()
+}
+
+#[test]
+fn replays_the_level() {
+    let data = level(TileId::Ground1);
+    let recording = record_on(&data);
+
+    // Played live
+    let mut live = machine(&data);
+    block_on(live.on_control(Event::Tick(0)));
+    block_on(live.enter_level(0));
+    let start = live.display().raw().to_vec();
+    for event in play() {
+        block_on(live.on_control(event));
+    }
+    assert!(live.display().raw() != start);
+
+    // Loaded from flash, as the device does at boot
+    let mut flash = RamFlash::with_image(&data.encode());
+    flash.put(ADDR_OFFSET + REPLAY_OFFSET, &recording);
+    let replay = block_on(Replay::load(&mut flash, REPLAY_OFFSET)).unwrap();
+    assert_eq!(replay.level(), 0);
+
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.replay(&replay)).unwrap();
+    assert!(sm.display().raw() == live.display().raw());
+}
+
+#[test]
+fn paced_by_recorded_ticks() {
+    let data = level(TileId::Ground1);
+    let replay = Replay::decode(&record_on(&data)).unwrap();
+    let ticks = replay
+        .events()
+        .filter(|event| matches!(event, Event::Tick(_)))
+        .count();
+    assert!(ticks >= 16);
+
+    // A wait before each tick, none before the buttons
+    let mut waits = 0;
+    let mut sm = machine(&data);
+    let wait = || {
+        waits += 1;
+        ready(())
+    };
+    block_on(sm.replay_paced(&replay, wait)).unwrap();
+    assert_eq!(waits, ticks);
+
+    let mut all_at_once = machine(&data);
+    block_on(all_at_once.replay(&replay)).unwrap();
+    assert!(sm.display().raw() == all_at_once.display().raw());
+}
+
+#[test]
+fn stale_level() {
+    let data = level(TileId::Ground1);
+    let replay = Replay::decode(&record_on(&data)).unwrap();
+
+    // Same level
+    assert_eq!(block_on(machine(&data).check_replay(&replay)), Ok(()));
+
+    // A tile changed
+    let mut changed = data.clone();
+    changed.tiles[4][4] = TileId::BrickWall1 as u8;
+    let mut sm = machine(&changed);
+    assert_eq!(
+        block_on(sm.replay(&replay)),
+        Err(ReplayError::StaleLevel(0))
+    );
+
+    // An item moved
+    let mut moved = LevelData::new();
+    moved.tiles = data.tiles;
+    moved
+        .push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    moved
+        .push_item(Placement::new(ItemKind::Exit, 9, 6))
+        .unwrap();
+    assert_eq!(
+        block_on(machine(&moved).check_replay(&replay)),
+        Err(ReplayError::StaleLevel(0))
+    );
+
+    // No such level, not even a built in one
+    let mut recorder = Recorder::new();
+    recorder.enter(Some(200));
+    let replay = Replay::decode(&recorder.encode().unwrap()).unwrap();
+    assert_eq!(
+        block_on(machine(&data).check_replay(&replay)),
+        Err(ReplayError::StaleLevel(200))
+    );
+}
@@ -0,0 +1,196 @@
+//! Save slots rotate over their sectors and survive broken records.
+
+use async_trait::async_trait;
+use embassy_futures::block_on;
+use koldun::game::flash::{Flash, FlashError, RamFlash, ADDR_OFFSET, SECTOR_SIZE};
+use koldun::game::save::{
+    decode_record, encode_record, slot_offset, Progress, SaveData, SaveError, SaveSlots,
+    HEADER_SIZE, MAX_LEVELS, RECORDS_PER_SECTOR, SAVE_OFFSET, SAVE_SECTORS, SLOTS,
+};
+use koldun::game::settings::{Choice, Theme};
+
+/// Counts erases per save sector
+struct CountingFlash {
+    flash: RamFlash,
+    erases: [usize; SAVE_SECTORS],
+}
+
+impl CountingFlash {
+    fn new() -> Self {
+        CountingFlash {
+            flash: RamFlash::new(),
+            erases: [0; SAVE_SECTORS],
+        }
+    }
+}
+
+#[async_trait]
+impl Flash for CountingFlash {
+    async fn load(&mut self, offset: usize, buf: &mut [u32]) {
+        self.flash.load(offset, buf).await
+    }
+
+    async fn load_tga<const SIZE_U32: usize, const SIZE_U8: usize>(
+        &mut self,
+        offset: usize,
+    ) -> Vec<u8> {
+        self.flash.load_tga::<SIZE_U32, SIZE_U8>(offset).await
+    }
+
+    async fn erase(&mut self, offset: usize, len: usize) -> Result<(), FlashError> {
+        self.erases[(offset - SAVE_OFFSET) / SECTOR_SIZE] += 1;
+        self.flash.erase(offset, len).await
+    }
+
+    async fn write(&mut self, offset: usize, data: &[u8]) -> Result<(), FlashError> {
+        self.flash.write(offset, data).await
+    }
+}
+
+fn save(level: usize) -> SaveData {
+    let mut data = SaveData::default();
+    for index in 0..level {
+        data.progress.complete(index, 10 + index as u16);
+    }
+    data.settings.theme = Theme::ALL[level % Theme::ALL.len()];
+    data
+}
+
+#[test]
+fn empty_flash() {
+    let mut flash = RamFlash::new();
+    let slots = block_on(SaveSlots::load(&mut flash));
+    assert!(slots.data().is_none());
+    assert_eq!(slots.sequence(), 0);
+}
+
+#[test]
+fn store_and_load() {
+    let mut flash = RamFlash::new();
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    block_on(slots.store(&mut flash, &save(3))).unwrap();
+    block_on(slots.store(&mut flash, &save(4))).unwrap();
+
+    let slots = block_on(SaveSlots::load(&mut flash));
+    assert_eq!(slots.data(), Some(&save(4)));
+    assert_eq!(slots.sequence(), 2);
+}
+
+#[test]
+fn progress() {
+    let mut progress = Progress::new();
+    assert!(progress.is_unlocked(0));
+    assert!(!progress.is_unlocked(1));
+    assert_eq!(progress.best_moves(0), None);
+    assert!(!progress.has_progress());
+
+    progress.complete(0, 20);
+    assert!(progress.has_progress());
+    progress.complete(0, 30);
+    assert!(progress.is_completed(0));
+    assert!(progress.is_unlocked(1));
+    assert!(!progress.is_completed(1));
+    assert_eq!(progress.best_moves(0), Some(20));
+
+    progress.complete(MAX_LEVELS - 1, 5);
+    assert_eq!(progress.unlocked(), MAX_LEVELS);
+    assert_eq!(progress.best_moves(MAX_LEVELS - 1), Some(5));
+}
+
+#[test]
+fn wear_leveling() {
+    let mut flash = CountingFlash::new();
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    for round in 0..SLOTS * 3 {
+        block_on(slots.store(&mut flash, &save(round % MAX_LEVELS))).unwrap();
+    }
+    assert_eq!(flash.erases, [3; SAVE_SECTORS]);
+
+    // Carries on after a restart without erasing the newest sector
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    assert_eq!(slots.sequence(), SLOTS as u32 * 3);
+    block_on(slots.store(&mut flash, &save(1))).unwrap();
+    assert_eq!(flash.erases, [4, 3, 3, 3]);
+    assert_eq!(block_on(SaveSlots::load(&mut flash)).data(), Some(&save(1)));
+}
+
+#[test]
+fn corrupted_record_falls_back() {
+    let mut flash = RamFlash::new();
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    block_on(slots.store(&mut flash, &save(2))).unwrap();
+    block_on(slots.store(&mut flash, &save(5))).unwrap();
+
+    // Flip a payload bit of the newest record
+    let mut record = flash.memory()[ADDR_OFFSET + slot_offset(1)..][..HEADER_SIZE + 1].to_vec();
+    record[HEADER_SIZE] ^= 1;
+    flash.put(ADDR_OFFSET + slot_offset(1), &record);
+
+    let slots = block_on(SaveSlots::load(&mut flash));
+    assert_eq!(slots.data(), Some(&save(2)));
+}
+
+#[test]
+fn torn_write_is_skipped() {
+    let mut flash = RamFlash::new();
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    block_on(slots.store(&mut flash, &save(2))).unwrap();
+
+    // Power lost halfway through the second record
+    let record = encode_record(2, &save(3));
+    block_on(flash.write(slot_offset(1), &record[..record.len() / 2])).unwrap();
+
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    assert_eq!(slots.data(), Some(&save(2)));
+
+    // The next save moves on to a fresh sector
+    block_on(slots.store(&mut flash, &save(4))).unwrap();
+    let slots = block_on(SaveSlots::load(&mut flash));
+    assert_eq!(slots.data(), Some(&save(4)));
+    assert_eq!(
+        decode_record(&flash.memory()[ADDR_OFFSET + slot_offset(RECORDS_PER_SECTOR)..][..256])
+            .map(|(sequence, _)| sequence),
+        Ok(2)
+    );
+}
+
+#[test]
+fn other_version_is_ignored() {
+    let mut flash = RamFlash::new();
+    let mut record = encode_record(7, &save(3));
+    record[4] = 0;
+    assert_eq!(
+        decode_record(&record),
+        Err(SaveError::UnsupportedVersion(0))
+    );
+
+    block_on(flash.erase(SAVE_OFFSET, SECTOR_SIZE)).unwrap();
+    block_on(flash.write(SAVE_OFFSET, &record)).unwrap();
+    let mut slots = block_on(SaveSlots::load(&mut flash));
+    assert!(slots.data().is_none());
+
+    // and gets replaced by the first save
+    block_on(slots.store(&mut flash, &save(1))).unwrap();
+    assert_eq!(block_on(SaveSlots::load(&mut flash)).data(), Some(&save(1)));
+}
+
+#[test]
+fn bad_progress() {
+    let mut record = encode_record(1, &save(0));
+    record[HEADER_SIZE] = 0;
+    let crc_at = record.len() - 4;
+    let crc = crc32(&record[..crc_at]);
+    record[crc_at..].copy_from_slice(&crc.to_le_bytes());
+    assert_eq!(decode_record(&record), Err(SaveError::BadProgress));
+}
+
+fn crc32(data: &[u8]) -> u32 {
+    let mut crc = u32::MAX;
+    for &byte in data {
+        crc ^= byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
@@ -0,0 +1,266 @@
+//! Tile ids and their metadata, which decides where the wizard can go.
+
+use embassy_futures::block_on;
+use embedded_graphics::pixelcolor::Rgb565;
+use embedded_graphics::prelude::Point;
+use heapless::Vec;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::colors::{Ink, TilePalette, DUNGEON, FOREST};
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::state_mashine::states::level::actions::{
+    Action, Actions, MoveDestination, Target, Who,
+};
+use koldun::game::state_mashine::states::level::grid::{Grid, MAX_EVENTS};
+use koldun::game::state_mashine::states::level::items::spell::Spell;
+use koldun::game::state_mashine::states::level::items::wizard::Wizard;
+use koldun::game::state_mashine::states::level::items::Item;
+use koldun::game::state_mashine::states::level::Level;
+use koldun::game::state_mashine::states::spell::SpellCommands;
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tiles::{TileId, TileMeta, UnknownTile};
+use koldun::game::{MAX_X, MAX_Y};
+use koldun_level_format::{FormatError, ItemKind, LevelData, Placement, HEIGHT, WIDTH};
+
+/// Wizard, `tiles`, exit in a row, with the level entered
+fn level_with(tiles: &[TileId]) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let mut data = LevelData::new();
+    data.tiles = [[TileId::Empty as u8; WIDTH]; HEIGHT];
+    for (x, tile) in tiles.iter().enumerate() {
+        data.tiles[1][2 + x] = *tile as u8;
+    }
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 2 + tiles.len() as u8, 1))
+        .unwrap();
+
+    let flash = RamFlash::with_image(&data.encode());
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.on_control(Event::Tick(0)));
+    block_on(sm.enter_level(0));
+    sm
+}
+
+/// Pixels of the cell at `x`, `y` on the display
+fn cell(
+    sm: &StateMachine<FramebufferDisplay, RamFlash>,
+    x: usize,
+    y: usize,
+) -> std::vec::Vec<Rgb565> {
+    (0..32 * 32)
+        .map(|i| sm.display().pixel(32 * x + i % 32, 32 * y + i / 32))
+        .collect()
+}
+
+/// Presses and releases right, then lets the move play out
+fn step_right(sm: &mut StateMachine<FramebufferDisplay, RamFlash>, tick: &mut u128) {
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Pressed))));
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Released))));
+    for _ in 0..20 {
+        *tick += 1;
+        block_on(sm.on_control(Event::Tick(*tick)));
+    }
+}
+
+/// The level of [`level_with`] is won by two steps right if `tile` can be
+/// walked over
+fn reaches_exit_over(tile: TileId) -> bool {
+    let mut sm = level_with(&[tile]);
+    let mut tick = 0;
+    for _ in 0..2 {
+        step_right(&mut sm, &mut tick);
+    }
+    sm.level() != Some(0)
+}
+
+#[test]
+fn floors_are_walked_over() {
+    assert!(reaches_exit_over(TileId::Ground1));
+    assert!(reaches_exit_over(TileId::DoorOpen));
+}
+
+#[test]
+fn walls_and_fences_stop_the_wizard() {
+    assert!(!reaches_exit_over(TileId::BrickWall1));
+    assert!(!reaches_exit_over(TileId::Fence));
+}
+
+#[test]
+fn hazards_restart_the_level() {
+    for hazard in [TileId::Web, TileId::Water] {
+        assert!(!reaches_exit_over(hazard));
+
+        // A step onto the floor, the next one into the hazard puts the
+        // wizard back at the start and leaves the floor empty again
+        let mut sm = level_with(&[TileId::Ground1, hazard]);
+        let floor = cell(&sm, 2, 1);
+        let mut tick = 0;
+        step_right(&mut sm, &mut tick);
+        assert_ne!(cell(&sm, 2, 1), floor);
+        step_right(&mut sm, &mut tick);
+        assert_eq!(sm.level(), Some(0));
+        assert_eq!(cell(&sm, 2, 1), floor);
+    }
+}
+
+/// Wizard at (1, 1) on an empty grid with `tile` right of it
+fn grid_with(tile: TileId) -> Grid {
+    let mut ids = [[TileId::Empty; MAX_X]; MAX_Y];
+    ids[1][2] = tile;
+    let mut grid: Grid = ids.into();
+    let wizard: Item<Wizard> = Item::new(Point::new(1, 1), 1, TileId::WizardIdle1);
+    grid.set_item(1, 1, Box::new(wizard));
+    grid
+}
+
+fn actions(action: Action) -> Vec<Action, MAX_EVENTS> {
+    let mut actions = Vec::new();
+    actions.push(action).unwrap();
+    actions
+}
+
+/// Whether the grid lets the wizard step right onto the next cell, and
+/// whether the level is lost by it
+fn steps_right(grid: &mut Grid) -> (bool, bool) {
+    let step = Actions::Move {
+        dest: MoveDestination::Right,
+        who: Who::Wizard,
+    };
+    let (_, _, _, _, is_lost) = grid.on_actions(actions(Action::new(Target::new(1, 1, 1), step)));
+    let entered = grid.item_tile_id(Target::new(2, 1, 1)) == Some(TileId::WizardIdle1);
+    (entered, is_lost)
+}
+
+/// `true` if a spell cast right by the wizard lands on the next cell. The spell
+/// waits in the corner until it is cast, as `Level::from_spell` leaves it
+fn spell_lands(grid: &mut Grid) -> bool {
+    let mut commands = Vec::new();
+    commands.push(SpellCommands::Right).unwrap();
+    let spell = Spell::new(Point::new(0, 0), 0, TileId::Fence, commands);
+    grid.set_item(0, 0, Box::new(spell));
+
+    let cast = Actions::InitSpell(MoveDestination::Right);
+    grid.on_actions(actions(Action::new(Target::new(0, 0, 0), cast)));
+    grid.item_tile_id(Target::new(2, 1, 1)) == Some(TileId::Fence)
+}
+
+#[test]
+fn grid_loses_the_level_in_hazards() {
+    assert_eq!(steps_right(&mut grid_with(TileId::Ground1)), (true, false));
+    assert_eq!(steps_right(&mut grid_with(TileId::Web)), (true, true));
+    assert_eq!(steps_right(&mut grid_with(TileId::Water)), (true, true));
+}
+
+#[test]
+fn fence_stops_walking_but_not_spells() {
+    assert_eq!(steps_right(&mut grid_with(TileId::Fence)), (false, false));
+    assert!(spell_lands(&mut grid_with(TileId::Fence)));
+    assert!(spell_lands(&mut grid_with(TileId::Water)));
+}
+
+#[test]
+fn walls_block_spells() {
+    assert!(!spell_lands(&mut grid_with(TileId::BrickWall1)));
+
+    // With the wall's sprite gone the layer is free, its terrain still stops the spell
+    let mut grid = grid_with(TileId::BrickWall1);
+    grid[1][2].take_item(1).unwrap();
+    assert!(!spell_lands(&mut grid));
+}
+
+#[test]
+fn metadata() {
+    assert_eq!(TileId::Ground2.meta(), TileMeta::FLOOR);
+
+    // Only walls take the layer the wizard moves on
+    let wall = TileId::Wall1.meta();
+    assert_eq!(wall.layer, 1);
+    assert!(!wall.walkable && wall.blocks_spells);
+
+    let fence = TileId::DoorFence.meta();
+    assert_eq!(fence.layer, 0);
+    assert!(!fence.walkable && !fence.blocks_spells);
+
+    assert!(TileId::Web.meta().hazard);
+
+    // Water drowns the wizard but a spell flies over it
+    let water = TileId::Water.meta();
+    assert_eq!(water.layer, 0);
+    assert!(water.walkable && water.hazard && !water.blocks_spells);
+}
+
+#[test]
+fn animation_frames() {
+    let idle = TileId::WizardIdle1.meta();
+    assert_eq!(idle.animated_with, Some(TileId::WizardIdle2));
+    assert_eq!(idle.default_fg, Ink::WizardFg);
+    assert_eq!(
+        TileId::WizardIdle2.meta().animated_with,
+        Some(TileId::WizardIdle1)
+    );
+    assert_eq!(TileId::Tree.meta().animated_with, None);
+}
+
+#[test]
+fn themed_colours() {
+    // A wall is drawn in the wall colours, a wizard in its own
+    assert_eq!(
+        TileId::BrickWall1.themed(&DUNGEON),
+        TileId::BrickWall1.render(&[DUNGEON.wall_bg, DUNGEON.wall_fg])
+    );
+    assert_eq!(
+        TileId::WizardUp1.themed(&FOREST),
+        TileId::WizardUp1.render(&[FOREST.wall_bg, FOREST.wizard_fg])
+    );
+}
+
+#[test]
+fn tile_palettes() {
+    // The first three sheets are two-colour, the fourth is 4bpp
+    assert_eq!(TileId::SHEET_DEPTHS, &[1, 1, 1, 4]);
+    assert_eq!(TileId::Tree.bits().len(), 128);
+    assert_eq!(TileId::Pillar.depth(), 4);
+    assert_eq!(TileId::Pillar.bits().len(), 512);
+    assert_eq!(TileId::Pillar.colors(&FOREST), FOREST.tiles);
+    assert_eq!(
+        TileId::Tree.colors(&FOREST),
+        TilePalette::pair(FOREST.wall_bg, FOREST.wall_fg)
+    );
+
+    // Indexed tiles shade from the theme's wall background up to its foreground
+    let shades = DUNGEON.tiles.0;
+    assert_eq!(shades[0], DUNGEON.wall_bg);
+    assert_eq!(shades[15], DUNGEON.wall_fg);
+    assert_eq!(FOREST.tiles.colors(4).len(), 4);
+
+    // Cobbles go from mortar at index 2 to stone lit up to index 13
+    let cobbles = TileId::Cobbles.bits();
+    assert_eq!(cobbles[0] & 0x0f, 2);
+    assert_eq!(cobbles.iter().map(|byte| byte >> 4).max(), Some(13));
+}
+
+#[test]
+fn ids() {
+    assert_eq!(TileId::BrickWall1 as u16, 32 + 4);
+    assert_eq!(TileId::BrickWall1.name(), "BRICK_WALL1");
+    assert_eq!(format!("{:?}", TileId::WizardUp2), "WIZARD_UP2");
+
+    for tile in TileId::ALL {
+        assert_eq!(TileId::try_from(*tile as u16), Ok(*tile));
+    }
+    assert_eq!(TileId::try_from(31), Err(UnknownTile(31)));
+}
+
+#[test]
+fn unknown_tile_breaks_level() {
+    let mut data = LevelData::new();
+    data.tiles[4][4] = 31;
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 3, 1))
+        .unwrap();
+    assert!(matches!(
+        Level::from_data(0, &data),
+        Err(FormatError::UnknownTile(31))
+    ));
+}
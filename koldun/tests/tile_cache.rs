@@ -0,0 +1,104 @@
+//! The tile cache keeps the tiles drawn last within its budget and renders the rest again.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::colors::{DUNGEON, FOREST};
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tile_cache::{TileCache, TileKey, DEFAULT_BUDGET, TILE_BYTES};
+use koldun::game::tiles::TileId;
+
+fn key(id: TileId) -> TileKey {
+    TileKey::themed(id, &DUNGEON)
+}
+
+#[test]
+fn renders_on_miss() {
+    let mut cache = TileCache::new(DEFAULT_BUDGET);
+    assert_eq!(*cache.get(key(TileId::Tree)), TileId::Tree.themed(&DUNGEON));
+    cache.get(key(TileId::Tree));
+    assert_eq!((cache.hits(), cache.misses()), (1, 1));
+    assert_eq!(cache.used(), TILE_BYTES);
+}
+
+#[test]
+fn colours_are_part_of_the_key() {
+    let mut cache = TileCache::new(DEFAULT_BUDGET);
+    cache.themed(TileId::Tree, &DUNGEON);
+    assert!(!cache.contains(&TileKey::themed(TileId::Tree, &FOREST)));
+    assert_eq!(
+        *cache.themed(TileId::Tree, &FOREST),
+        TileId::Tree.themed(&FOREST)
+    );
+    assert_eq!(cache.len(), 2);
+}
+
+#[test]
+fn least_recently_used_evicted() {
+    let mut cache = TileCache::new(2 * TILE_BYTES);
+    cache.get(key(TileId::Tree));
+    cache.get(key(TileId::Stone1));
+    cache.get(key(TileId::Tree));
+    cache.get(key(TileId::Web));
+
+    assert!(cache.contains(&key(TileId::Tree)));
+    assert!(!cache.contains(&key(TileId::Stone1)));
+    assert!(cache.contains(&key(TileId::Web)));
+    assert_eq!(cache.used(), 2 * TILE_BYTES);
+
+    // Shrinking keeps the newest
+    cache.set_budget(TILE_BYTES);
+    assert!(cache.contains(&key(TileId::Web)));
+    assert_eq!(cache.len(), 1);
+
+    // The tile asked for is kept even if it doesn't fit
+    cache.set_budget(0);
+    assert_eq!(*cache.get(key(TileId::Tree)), TileId::Tree.themed(&DUNGEON));
+    assert_eq!(cache.len(), 1);
+}
+
+fn ruins(budget: usize) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), RamFlash::new());
+    sm.tiles_mut().set_budget(budget);
+    block_on(sm.on_control(Event::Tick(0)));
+    block_on(sm.enter_level(0));
+    sm
+}
+
+#[test]
+fn level_within_budget() {
+    let sm = ruins(DEFAULT_BUDGET);
+    assert!(!sm.tiles().is_empty());
+    assert!(sm.tiles().used() <= DEFAULT_BUDGET);
+
+    // A budget of a few tiles draws the same level
+    let small = ruins(4 * TILE_BYTES);
+    assert!(small.tiles().used() <= 4 * TILE_BYTES);
+    assert!(small.display().raw() == sm.display().raw());
+
+    // Without a cache tiles are streamed
+    let streamed = ruins(0);
+    assert!(streamed.tiles().is_empty());
+    assert!(streamed.display().raw() == sm.display().raw());
+}
+
+/// Wizard partway through a step right, drawn over two cells on the canvas
+fn walking(budget: usize) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let mut sm = ruins(budget);
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Pressed))));
+    block_on(sm.on_control(Event::Button(Buttons::Right(States::Released))));
+    for tick in 1..=6 {
+        block_on(sm.on_control(Event::Tick(tick)));
+    }
+    sm
+}
+
+#[test]
+fn canvas_without_cache() {
+    // Layers and the moving sprite come from the tiles' bits, nothing is cached
+    let streamed = walking(0);
+    assert!(streamed.tiles().is_empty());
+    assert_eq!(streamed.tiles().misses(), 0);
+    assert!(streamed.display().raw() == walking(DEFAULT_BUDGET).display().raw());
+}
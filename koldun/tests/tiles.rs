@@ -0,0 +1,82 @@
+//! Levels render the tiles they use, in their own theme when they have one.
+
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::colors::{DUNGEON, FOREST};
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::RamFlash;
+use koldun::game::state_mashine::StateMachine;
+use koldun::game::tiles::TileId;
+use koldun_level_format::{ItemKind, LevelData, Placement, HEIGHT, WIDTH};
+
+/// Wizard next to the exit, the rest is empty
+fn level() -> LevelData {
+    let mut data = LevelData::new();
+    data.tiles = [[TileId::Empty as u8; WIDTH]; HEIGHT];
+    data.push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Exit, 2, 1))
+        .unwrap();
+    data
+}
+
+fn start(data: &LevelData) -> StateMachine<FramebufferDisplay, RamFlash> {
+    let flash = RamFlash::with_image(&data.encode());
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    block_on(sm.on_control(Event::Tick(0)));
+    block_on(sm.enter_level(0));
+    assert_eq!(sm.level(), Some(0));
+    sm
+}
+
+#[test]
+fn every_tile_drawn() {
+    // All the tiles there are, from the third row on
+    let mut data = level();
+    let cells = data.tiles.iter_mut().skip(2).flatten();
+    for (cell, tile) in cells.zip(TileId::ALL) {
+        *cell = *tile as u8;
+    }
+    data.push_item(Placement::new(ItemKind::Spider, 4, 1))
+        .unwrap();
+    data.push_item(Placement::new(ItemKind::Door, 6, 1))
+        .unwrap();
+
+    let mut sm = start(&data);
+
+    // The wizard turns through its frames
+    let mut tick = 0;
+    for button in [Buttons::Down, Buttons::Up, Buttons::Left, Buttons::Right] {
+        block_on(sm.on_control(Event::Button(button(States::Pressed))));
+        block_on(sm.on_control(Event::Button(button(States::Released))));
+        for _ in 0..20 {
+            tick += 1;
+            block_on(sm.on_control(Event::Tick(tick)));
+        }
+    }
+}
+
+#[test]
+fn level_theme() {
+    let mut data = level();
+    let empty = (240, 250);
+    let sm = start(&data);
+    assert_eq!(sm.display().pixel(empty.0, empty.1), DUNGEON.wall_bg);
+
+    data.theme = Some(1);
+    let sm = start(&data);
+    assert_eq!(sm.display().pixel(empty.0, empty.1), FOREST.wall_bg);
+}
+
+#[test]
+fn theme_saved() {
+    let mut data = level();
+    data.theme = Some(2);
+    let decoded = LevelData::decode(&data.encode()).unwrap();
+    assert_eq!(decoded.theme, Some(2));
+
+    // A theme from a newer build is left to the player
+    let mut image = data.encode();
+    image[koldun_level_format::THEME_OFFSET] = 9;
+    assert_eq!(LevelData::decode(&image).unwrap().theme, None);
+}
@@ -0,0 +1,116 @@
+use koldun_level_format::{LevelData, THEMES};
+use source::compile;
+use std::collections::BTreeSet;
+use std::env;
+use std::fs;
+use std::path::Path;
+use std::process::ExitCode;
+use tiles::TileNames;
+
+mod source;
+mod tiled;
+mod tiles;
+
+const USAGE: &str = "usage: koldun_level_compiler <levels.bin> <level.lvl|level.tmx|level.json>...";
+
+fn main() -> ExitCode {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((output, sources)) = args.split_first() else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    if sources.is_empty() {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    }
+
+    let tiles = match TileNames::from_firmware() {
+        Ok(tiles) => tiles,
+        Err(err) => {
+            eprintln!("error: tiles.rs: {}", err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut blob: Vec<u8> = Vec::new();
+    let mut failed = false;
+
+    for (index, path) in sources.iter().enumerate() {
+        let source = match fs::read_to_string(path) {
+            Ok(source) => source,
+            Err(err) => {
+                eprintln!("error: {}: {}", path, err);
+                failed = true;
+                continue;
+            }
+        };
+
+        match load(path, &source, &tiles) {
+            Ok(level) => {
+                report(index, path, &level, &tiles);
+                blob.extend_from_slice(&level.encode());
+            }
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("error: {}", error);
+                }
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        eprintln!("{} not written", output);
+        return ExitCode::FAILURE;
+    }
+
+    if let Err(err) = fs::write(output, &blob) {
+        eprintln!("error: {}: {}", output, err);
+        return ExitCode::FAILURE;
+    }
+    println!(
+        "{} levels, {} bytes written to {}",
+        sources.len(),
+        blob.len(),
+        output
+    );
+    ExitCode::SUCCESS
+}
+
+fn load(path: &str, source: &str, tiles: &TileNames) -> Result<LevelData, Vec<String>> {
+    match Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        Some("tmx") | Some("json") | Some("tmj") => tiled::import(Path::new(path), source, tiles)
+            .map_err(|errors| {
+                errors
+                    .iter()
+                    .map(|error| format!("{}: {}", path, error))
+                    .collect()
+            }),
+        _ => compile(source, tiles).map_err(|errors| {
+            errors
+                .iter()
+                .map(|error| match error.line {
+                    0 => format!("{}: {}", path, error.kind),
+                    line => format!("{}:{}: {}", path, line, error.kind),
+                })
+                .collect()
+        }),
+    }
+}
+
+fn report(index: usize, path: &str, level: &LevelData, tiles: &TileNames) {
+    println!("[{}] {} \"{}\"", index, path, level.name());
+
+    let used: BTreeSet<u8> = level.tiles.iter().flatten().copied().collect();
+    let names: Vec<&str> = used
+        .iter()
+        .map(|id| tiles.name(*id).unwrap_or("?"))
+        .collect();
+    println!("    tiles: {}", names.join(", "));
+    if let Some(theme) = level.theme {
+        println!("    theme: {}", THEMES[theme as usize]);
+    }
+
+    for item in level.items() {
+        println!("    {} at ({}, {})", item.kind.name(), item.x, item.y);
+    }
+}
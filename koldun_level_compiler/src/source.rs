@@ -0,0 +1,329 @@
+//! Human-editable level sources.
+//!
+//! ```text
+//! // Comments start with two slashes
+//! name = Ruins
+//! floor = EMPTY
+//! theme = forest
+//!
+//! [legend]
+//! . = EMPTY
+//! # = BRICK_WALL1
+//! W = wizard
+//! E = exit GROUND1
+//!
+//! [map]
+//! T...#....%.....
+//! ...
+//! ```
+//!
+//! Legend values are either tile names from `tiles.rs` or an item kind
+//! (`wizard`, `exit`, `spider`, `door`) optionally followed by the tile drawn under it,
+//! `floor` is used when it is omitted. `theme` is one of `dungeon`, `forest` or `ice`,
+//! without it the level is drawn in the theme the player picked.
+
+use crate::tiles::TileNames;
+use koldun_level_format::{FormatError, ItemKind, LevelData, Placement, HEIGHT, THEMES, WIDTH};
+use std::collections::HashMap;
+use std::fmt;
+
+#[derive(Clone, Copy)]
+enum Glyph {
+    Tile(u8),
+    Item(ItemKind, Option<u8>),
+}
+
+enum Section {
+    Header,
+    Legend,
+    Map,
+}
+
+#[derive(Debug)]
+pub struct SourceError {
+    /// 1-based line number, 0 for errors about the level as a whole
+    pub line: usize,
+    pub kind: ErrorKind,
+}
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    UnknownSection(String),
+    UnknownKey(String),
+    BadLine(String),
+    UnknownTile(String),
+    UnknownItem(String),
+    UnknownTheme(String),
+    DuplicateGlyph(char),
+    UnknownGlyph(char),
+    OutOfBounds(char, usize, usize),
+    ShortRow(usize),
+    MissingRows(usize),
+    Format(FormatError),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::UnknownSection(name) => write!(f, "unknown section `[{}]`", name),
+            ErrorKind::UnknownKey(key) => write!(f, "unknown key `{}`", key),
+            ErrorKind::BadLine(line) => write!(f, "expected `key = value`, got `{}`", line),
+            ErrorKind::UnknownTile(name) => write!(f, "unknown tile name `{}`", name),
+            ErrorKind::UnknownItem(name) => write!(f, "unknown item `{}`", name),
+            ErrorKind::UnknownTheme(name) => write!(f, "unknown theme `{}`", name),
+            ErrorKind::DuplicateGlyph(glyph) => write!(f, "glyph `{}` defined twice", glyph),
+            ErrorKind::UnknownGlyph(glyph) => write!(f, "glyph `{}` is not in the legend", glyph),
+            ErrorKind::OutOfBounds(glyph, x, y) => write!(
+                f,
+                "`{}` at ({}, {}) is outside of the {}x{} level",
+                glyph, x, y, WIDTH, HEIGHT
+            ),
+            ErrorKind::ShortRow(len) => write!(f, "row has {} cells, expected {}", len, WIDTH),
+            ErrorKind::MissingRows(rows) => write!(f, "map has {} rows, expected {}", rows, HEIGHT),
+            ErrorKind::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+pub fn compile(source: &str, tiles: &TileNames) -> Result<LevelData, Vec<SourceError>> {
+    let mut errors = Vec::new();
+    let mut error = |line: usize, kind: ErrorKind| errors.push(SourceError { line, kind });
+
+    let mut level = LevelData::new();
+    let mut floor = tiles.get("EMPTY").unwrap_or(0);
+    let mut legend: HashMap<char, Glyph> = HashMap::new();
+    let mut section = Section::Header;
+    let mut y = 0;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = match name {
+                "legend" => Section::Legend,
+                "map" => Section::Map,
+                _ => {
+                    error(line_no, ErrorKind::UnknownSection(name.to_string()));
+                    section
+                }
+            };
+            continue;
+        }
+
+        match section {
+            Section::Header => {
+                let Some((key, value)) = line.split_once('=') else {
+                    error(line_no, ErrorKind::BadLine(line.to_string()));
+                    continue;
+                };
+                let value = value.trim();
+                match key.trim() {
+                    "name" => {
+                        if let Err(err) = level.set_name(value) {
+                            error(line_no, ErrorKind::Format(err));
+                        }
+                    }
+                    "floor" => match tiles.get(value) {
+                        Some(id) => floor = id,
+                        None => error(line_no, ErrorKind::UnknownTile(value.to_string())),
+                    },
+                    "theme" => match THEMES.iter().position(|theme| *theme == value) {
+                        Some(index) => level.theme = Some(index as u8),
+                        None => error(line_no, ErrorKind::UnknownTheme(value.to_string())),
+                    },
+                    key => error(line_no, ErrorKind::UnknownKey(key.to_string())),
+                }
+            }
+
+            Section::Legend => {
+                let mut chars = line.chars();
+                let glyph = chars.next().unwrap();
+                let Some(value) = chars.as_str().trim().strip_prefix('=') else {
+                    error(line_no, ErrorKind::BadLine(line.to_string()));
+                    continue;
+                };
+
+                match parse_glyph(value.trim(), tiles) {
+                    Ok(parsed) => {
+                        if legend.insert(glyph, parsed).is_some() {
+                            error(line_no, ErrorKind::DuplicateGlyph(glyph));
+                        }
+                    }
+                    Err(kind) => error(line_no, kind),
+                }
+            }
+
+            Section::Map => {
+                let mut width = 0;
+                for (x, glyph) in line.chars().enumerate() {
+                    width = x + 1;
+                    let Some(parsed) = legend.get(&glyph).copied() else {
+                        error(line_no, ErrorKind::UnknownGlyph(glyph));
+                        continue;
+                    };
+                    if x >= WIDTH || y >= HEIGHT {
+                        error(line_no, ErrorKind::OutOfBounds(glyph, x, y));
+                        continue;
+                    }
+
+                    match parsed {
+                        Glyph::Tile(id) => level.tiles[y][x] = id,
+                        Glyph::Item(kind, under) => {
+                            level.tiles[y][x] = under.unwrap_or(floor);
+                            let placement = Placement::new(kind, x as u8, y as u8);
+                            if let Err(err) = level.push_item(placement) {
+                                error(line_no, ErrorKind::Format(err));
+                            }
+                        }
+                    }
+                }
+                if width < WIDTH {
+                    error(line_no, ErrorKind::ShortRow(width));
+                }
+                y += 1;
+            }
+        }
+    }
+
+    if y < HEIGHT {
+        error(0, ErrorKind::MissingRows(y));
+    }
+
+    if let Err(err) = level.validate() {
+        error(0, ErrorKind::Format(err));
+    }
+
+    match errors.is_empty() {
+        true => Ok(level),
+        false => Err(errors),
+    }
+}
+
+fn parse_glyph(value: &str, tiles: &TileNames) -> Result<Glyph, ErrorKind> {
+    let mut words = value.split_whitespace();
+    let name = words.next().unwrap_or_default();
+
+    if !name.chars().any(|c| c.is_ascii_lowercase()) {
+        return tiles
+            .get(name)
+            .map(Glyph::Tile)
+            .ok_or(ErrorKind::UnknownTile(name.to_string()));
+    }
+    let kind = ItemKind::from_name(name).ok_or(ErrorKind::UnknownItem(name.to_string()))?;
+
+    match words.next() {
+        Some(under) => tiles
+            .get(under)
+            .map(|id| Glyph::Item(kind, Some(id)))
+            .ok_or(ErrorKind::UnknownTile(under.to_string())),
+        None => Ok(Glyph::Item(kind, None)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LEGEND: &str = "[legend]\n. = EMPTY\n# = BRICK_WALL1\nW = wizard\nE = exit GROUND1\n";
+
+    fn tiles() -> TileNames {
+        TileNames::from_firmware().unwrap()
+    }
+
+    /// A source with the given map rows under the legend
+    fn source(rows: &[&str]) -> String {
+        format!("name = Test\n{}[map]\n{}\n", LEGEND, rows.join("\n"))
+    }
+
+    /// `HEIGHT` rows, `top` first and the rest empty
+    fn rows(top: &[&'static str]) -> Vec<&'static str> {
+        let empty = "...............";
+        let mut rows = top.to_vec();
+        rows.resize(HEIGHT, empty);
+        rows
+    }
+
+    fn errors(source: &str) -> Vec<(usize, ErrorKind)> {
+        match compile(source, &tiles()) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors.into_iter().map(|err| (err.line, err.kind)).collect(),
+        }
+    }
+
+    #[test]
+    fn compiles() {
+        let level = compile(&source(&rows(&["#W.E..........."])), &tiles()).unwrap();
+        assert_eq!(level.name(), "Test");
+        assert_eq!(level.tiles[0][0], tiles().get("BRICK_WALL1").unwrap());
+        assert_eq!(level.tiles[0][3], tiles().get("GROUND1").unwrap());
+        assert_eq!(level.items().count(), 2);
+    }
+
+    #[test]
+    fn unknown_tile_name() {
+        let source = source(&rows(&["#W.E..........."])).replace("BRICK_WALL1", "BRICK_WALL9");
+        let errors = errors(&source);
+        assert!(
+            matches!(&errors[..], [(4, ErrorKind::UnknownTile(name)), ..] if name == "BRICK_WALL9")
+        );
+    }
+
+    #[test]
+    fn missing_wizard() {
+        let errors = errors(&source(&rows(&["#..E..........."])));
+        assert!(matches!(
+            &errors[..],
+            [(0, ErrorKind::Format(FormatError::MissingWizard))]
+        ));
+    }
+
+    #[test]
+    fn missing_exit() {
+        let errors = errors(&source(&rows(&["#W............."])));
+        assert!(matches!(
+            &errors[..],
+            [(0, ErrorKind::Format(FormatError::MissingExit))]
+        ));
+    }
+
+    #[test]
+    fn out_of_bounds() {
+        // A row past the last one, with the wizard on it
+        let mut map = rows(&["#..E..........."]);
+        map.push("W..............");
+        let errors = errors(&source(&map));
+        assert!(errors
+            .iter()
+            .any(|(_, kind)| matches!(kind, ErrorKind::OutOfBounds('W', 0, y) if *y == HEIGHT)));
+    }
+
+    #[test]
+    fn short_row() {
+        let errors = errors(&source(&rows(&["#W.E.........."])));
+        assert!(matches!(&errors[..], [(_, ErrorKind::ShortRow(len))] if *len == WIDTH - 1));
+    }
+
+    #[test]
+    fn long_row() {
+        let errors = errors(&source(&rows(&["#W.E............"])));
+        assert!(matches!(&errors[..], [(_, ErrorKind::OutOfBounds('.', x, 0))] if *x == WIDTH));
+    }
+
+    #[test]
+    fn missing_rows() {
+        let errors = errors(&source(&["#W.E..........."]));
+        assert!(matches!(&errors[..], [(0, ErrorKind::MissingRows(1))]));
+    }
+
+    #[test]
+    fn unknown_glyph_and_theme() {
+        let source = format!("theme = lava\n{}", source(&rows(&["#W.E.....?....."])));
+        let errors = errors(&source);
+        assert!(matches!(&errors[0], (1, ErrorKind::UnknownTheme(name)) if name == "lava"));
+        assert!(matches!(&errors[1], (_, ErrorKind::UnknownGlyph('?'))));
+    }
+}
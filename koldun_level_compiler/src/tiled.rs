@@ -0,0 +1,545 @@
+//! Importer for maps drawn in [Tiled](https://www.mapeditor.org), both `.tmx` and `.json`.
+//!
+//! The map must be `WIDTH`x`HEIGHT` tiles of 32x32 pixels, tile layers are stacked
+//! in order and must be saved as CSV (the default). Each tileset is one of the
+//! firmware tile sheets: a tileset named `tiles1` (or stored in `tiles1.tsx`) is
+//! sheet 1, so its tile 4 is `BRICK_WALL1`. An integer `sheet` property on the
+//! tileset takes precedence over the name.
+//!
+//! Objects on object layers become items, matched by class (`type` in older
+//! Tiled versions) or, if that is empty, by name: `wizard`, `exit`, `spider`, `door`.
+//! A level name can be set with a `name` map property, the file name is used otherwise.
+//! A `theme` map property (`dungeon`, `forest` or `ice`) sets the level's theme.
+
+use crate::tiles::TileNames;
+use crate::tiles::TILE_SIZE;
+use koldun_level_format::{FormatError, ItemKind, LevelData, Placement, HEIGHT, THEMES, WIDTH};
+use roxmltree::{Document, Node};
+use serde_json::Value;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+const FLIP_FLAGS: u32 = 0xf000_0000;
+
+#[derive(Debug)]
+pub enum TiledError {
+    Parse(String),
+    Unsupported(&'static str),
+    BadSize(usize, usize),
+    BadTileSize(u32, u32),
+    NoTileLayer,
+    BadLayerSize(usize),
+    BadTileset(String),
+    UnknownGid(u32),
+    UnknownTile(u8, u32),
+    Flipped(usize, usize),
+    UnknownObject(String),
+    UnknownTheme(String),
+    OutOfBounds(String, i64, i64),
+    Format(FormatError),
+}
+
+impl fmt::Display for TiledError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TiledError::Parse(err) => write!(f, "{}", err),
+            TiledError::Unsupported(what) => write!(f, "{} are not supported", what),
+            TiledError::BadSize(width, height) => write!(
+                f,
+                "map is {}x{} tiles, expected {}x{}",
+                width, height, WIDTH, HEIGHT
+            ),
+            TiledError::BadTileSize(width, height) => write!(
+                f,
+                "tiles are {}x{} pixels, expected {}x{}",
+                width, height, TILE_SIZE, TILE_SIZE
+            ),
+            TiledError::NoTileLayer => write!(f, "map has no tile layer"),
+            TiledError::BadLayerSize(len) => write!(
+                f,
+                "tile layer has {} tiles, expected {}",
+                len,
+                WIDTH * HEIGHT
+            ),
+            TiledError::BadTileset(name) => write!(
+                f,
+                "tileset `{}` is not a tile sheet, name it `tilesN` or set a `sheet` property",
+                name
+            ),
+            TiledError::UnknownGid(gid) => write!(f, "tile gid {} belongs to no tileset", gid),
+            TiledError::UnknownTile(sheet, index) => {
+                write!(
+                    f,
+                    "tile {} of sheet {} is not declared in tiles.rs",
+                    index, sheet
+                )
+            }
+            TiledError::Flipped(x, y) => write!(f, "tile at ({}, {}) is flipped or rotated", x, y),
+            TiledError::UnknownObject(name) => write!(f, "unknown object `{}`", name),
+            TiledError::UnknownTheme(name) => write!(f, "unknown theme `{}`", name),
+            TiledError::OutOfBounds(name, x, y) => {
+                write!(f, "`{}` at ({}, {}) is outside of the map", name, x, y)
+            }
+            TiledError::Format(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+struct Tileset {
+    firstgid: u32,
+    name: String,
+    sheet: Option<u8>,
+}
+
+struct Object {
+    kind: String,
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    is_tile: bool,
+}
+
+struct TiledMap {
+    width: usize,
+    height: usize,
+    tile_width: u32,
+    tile_height: u32,
+    name: Option<String>,
+    theme: Option<String>,
+    tilesets: Vec<Tileset>,
+    layers: Vec<Vec<u32>>,
+    objects: Vec<Object>,
+}
+
+pub fn import(path: &Path, text: &str, tiles: &TileNames) -> Result<LevelData, Vec<TiledError>> {
+    let map = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("json") | Some("tmj") => parse_json(text),
+        _ => parse_tmx(text),
+    }
+    .map_err(|err| vec![err])?;
+
+    let default_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default();
+    convert(map, default_name, tiles)
+}
+
+fn convert(
+    map: TiledMap,
+    default_name: &str,
+    tiles: &TileNames,
+) -> Result<LevelData, Vec<TiledError>> {
+    if map.width != WIDTH || map.height != HEIGHT {
+        return Err(vec![TiledError::BadSize(map.width, map.height)]);
+    }
+    if map.tile_width != TILE_SIZE || map.tile_height != TILE_SIZE {
+        return Err(vec![TiledError::BadTileSize(
+            map.tile_width,
+            map.tile_height,
+        )]);
+    }
+    if map.layers.is_empty() {
+        return Err(vec![TiledError::NoTileLayer]);
+    }
+    if let Some(layer) = map
+        .layers
+        .iter()
+        .find(|layer| layer.len() != WIDTH * HEIGHT)
+    {
+        return Err(vec![TiledError::BadLayerSize(layer.len())]);
+    }
+
+    let mut errors = Vec::new();
+    let mut level = LevelData::new();
+
+    let name = map.name.as_deref().unwrap_or(default_name);
+    if let Err(err) = level.set_name(name) {
+        errors.push(TiledError::Format(err));
+    }
+
+    if let Some(theme) = map.theme {
+        match THEMES.iter().position(|name| *name == theme) {
+            Some(index) => level.theme = Some(index as u8),
+            None => errors.push(TiledError::UnknownTheme(theme)),
+        }
+    }
+
+    for layer in map.layers.iter() {
+        for (i, gid) in layer.iter().enumerate() {
+            let (x, y) = (i % WIDTH, i / WIDTH);
+            if *gid == 0 {
+                continue;
+            }
+            if gid & FLIP_FLAGS != 0 {
+                errors.push(TiledError::Flipped(x, y));
+                continue;
+            }
+            match tile_id(*gid, &map.tilesets, tiles) {
+                Ok(id) => level.tiles[y][x] = id,
+                Err(err) => errors.push(err),
+            }
+        }
+    }
+
+    for object in map.objects.iter() {
+        let Some(kind) = ItemKind::from_name(&object.kind) else {
+            errors.push(TiledError::UnknownObject(object.kind.clone()));
+            continue;
+        };
+
+        // Tile objects are anchored at their bottom left corner, everything else at the top left
+        let top = match object.is_tile {
+            true => object.y - object.height,
+            false => object.y,
+        };
+        let x = ((object.x + object.width / 2.0) / TILE_SIZE as f64).floor() as i64;
+        let y = ((top + object.height / 2.0) / TILE_SIZE as f64).floor() as i64;
+
+        if x < 0 || y < 0 || x >= WIDTH as i64 || y >= HEIGHT as i64 {
+            errors.push(TiledError::OutOfBounds(object.kind.clone(), x, y));
+            continue;
+        }
+        if let Err(err) = level.push_item(Placement::new(kind, x as u8, y as u8)) {
+            errors.push(TiledError::Format(err));
+        }
+    }
+
+    if let Err(err) = level.validate() {
+        errors.push(TiledError::Format(err));
+    }
+
+    match errors.is_empty() {
+        true => Ok(level),
+        false => Err(errors),
+    }
+}
+
+fn tile_id(gid: u32, tilesets: &[Tileset], tiles: &TileNames) -> Result<u8, TiledError> {
+    let tileset = tilesets
+        .iter()
+        .filter(|tileset| tileset.firstgid <= gid)
+        .max_by_key(|tileset| tileset.firstgid)
+        .ok_or(TiledError::UnknownGid(gid))?;
+
+    let sheet = tileset
+        .sheet
+        .or_else(|| tileset.name.strip_prefix("tiles")?.parse().ok())
+        .ok_or_else(|| TiledError::BadTileset(tileset.name.clone()))?;
+    let index = gid - tileset.firstgid;
+
+    (sheet as u32 * 32)
+        .checked_add(index)
+        .filter(|_| index < 32)
+        .and_then(|id| u8::try_from(id).ok())
+        .filter(|id| tiles.name(*id).is_some())
+        .ok_or(TiledError::UnknownTile(sheet, index))
+}
+
+fn parse_tmx(text: &str) -> Result<TiledMap, TiledError> {
+    let doc = Document::parse(text).map_err(|err| TiledError::Parse(err.to_string()))?;
+    let root = doc.root_element();
+
+    let mut map = TiledMap {
+        width: attr(&root, "width")?,
+        height: attr(&root, "height")?,
+        tile_width: attr(&root, "tilewidth")?,
+        tile_height: attr(&root, "tileheight")?,
+        name: tmx_property(&root, "name"),
+        theme: tmx_property(&root, "theme"),
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    for node in root.children().filter(|node| node.is_element()) {
+        match node.tag_name().name() {
+            "tileset" => {
+                let name = match (node.attribute("name"), node.attribute("source")) {
+                    (Some(name), _) => name.to_string(),
+                    (None, Some(source)) => file_stem(source),
+                    (None, None) => String::new(),
+                };
+                map.tilesets.push(Tileset {
+                    firstgid: attr(&node, "firstgid")?,
+                    name,
+                    sheet: tmx_property(&node, "sheet").and_then(|sheet| sheet.parse().ok()),
+                });
+            }
+
+            "layer" => {
+                let data = node
+                    .children()
+                    .find(|child| child.has_tag_name("data"))
+                    .ok_or(TiledError::NoTileLayer)?;
+
+                let gids = match data.attribute("encoding") {
+                    Some("csv") => data
+                        .text()
+                        .unwrap_or_default()
+                        .split(',')
+                        .map(|gid| gid.trim().parse::<u32>())
+                        .collect::<Result<Vec<u32>, _>>()
+                        .map_err(|err| TiledError::Parse(err.to_string()))?,
+                    Some(_) => return Err(TiledError::Unsupported("Base64 encoded layers")),
+                    None => data
+                        .children()
+                        .filter(|child| child.has_tag_name("tile"))
+                        .map(|tile| attr(&tile, "gid").or(Ok(0)))
+                        .collect::<Result<Vec<u32>, TiledError>>()?,
+                };
+                map.layers.push(gids);
+            }
+
+            "objectgroup" => {
+                for object in node.children().filter(|child| child.has_tag_name("object")) {
+                    let kind = ["class", "type", "name"]
+                        .iter()
+                        .find_map(|key| object.attribute(*key).filter(|value| !value.is_empty()))
+                        .unwrap_or_default();
+                    map.objects.push(Object {
+                        kind: kind.to_string(),
+                        x: attr(&object, "x")?,
+                        y: attr(&object, "y")?,
+                        width: attr(&object, "width").unwrap_or(0.0),
+                        height: attr(&object, "height").unwrap_or(0.0),
+                        is_tile: object.attribute("gid").is_some(),
+                    });
+                }
+            }
+
+            "group" => return Err(TiledError::Unsupported("Group layers")),
+            _ => (),
+        }
+    }
+    Ok(map)
+}
+
+fn attr<T: FromStr>(node: &Node, name: &str) -> Result<T, TiledError> {
+    let value = node.attribute(name).ok_or_else(|| {
+        TiledError::Parse(format!("<{}> has no `{}`", node.tag_name().name(), name))
+    })?;
+    value.parse().map_err(|_| {
+        TiledError::Parse(format!(
+            "<{}> has bad `{}`: {}",
+            node.tag_name().name(),
+            name,
+            value
+        ))
+    })
+}
+
+fn tmx_property(node: &Node, name: &str) -> Option<String> {
+    node.children()
+        .find(|child| child.has_tag_name("properties"))?
+        .children()
+        .find(|child| child.has_tag_name("property") && child.attribute("name") == Some(name))?
+        .attribute("value")
+        .map(str::to_string)
+}
+
+fn parse_json(text: &str) -> Result<TiledMap, TiledError> {
+    let root: Value =
+        serde_json::from_str(text).map_err(|err| TiledError::Parse(err.to_string()))?;
+
+    let mut map = TiledMap {
+        width: json_number(&root, "width")? as usize,
+        height: json_number(&root, "height")? as usize,
+        tile_width: json_number(&root, "tilewidth")? as u32,
+        tile_height: json_number(&root, "tileheight")? as u32,
+        name: json_property(&root, "name"),
+        theme: json_property(&root, "theme"),
+        tilesets: Vec::new(),
+        layers: Vec::new(),
+        objects: Vec::new(),
+    };
+
+    for tileset in root["tilesets"].as_array().into_iter().flatten() {
+        let name = match (tileset["name"].as_str(), tileset["source"].as_str()) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(source)) => file_stem(source),
+            (None, None) => String::new(),
+        };
+        map.tilesets.push(Tileset {
+            firstgid: json_number(tileset, "firstgid")? as u32,
+            name,
+            sheet: json_property(tileset, "sheet").and_then(|sheet| sheet.parse().ok()),
+        });
+    }
+
+    for layer in root["layers"].as_array().into_iter().flatten() {
+        match layer["type"].as_str() {
+            Some("tilelayer") => {
+                let Some(data) = layer["data"].as_array() else {
+                    return Err(TiledError::Unsupported("Base64 encoded layers"));
+                };
+                let gids = data
+                    .iter()
+                    .map(|gid| gid.as_u64().map(|gid| gid as u32))
+                    .collect::<Option<Vec<u32>>>()
+                    .ok_or(TiledError::Parse("bad tile layer data".to_string()))?;
+                map.layers.push(gids);
+            }
+
+            Some("objectgroup") => {
+                for object in layer["objects"].as_array().into_iter().flatten() {
+                    let kind = ["class", "type", "name"]
+                        .iter()
+                        .find_map(|key| object[*key].as_str().filter(|value| !value.is_empty()))
+                        .unwrap_or_default();
+                    map.objects.push(Object {
+                        kind: kind.to_string(),
+                        x: json_number(object, "x")?,
+                        y: json_number(object, "y")?,
+                        width: object["width"].as_f64().unwrap_or(0.0),
+                        height: object["height"].as_f64().unwrap_or(0.0),
+                        is_tile: object.get("gid").is_some(),
+                    });
+                }
+            }
+
+            Some("group") => return Err(TiledError::Unsupported("Group layers")),
+            _ => (),
+        }
+    }
+    Ok(map)
+}
+
+fn json_number(value: &Value, name: &str) -> Result<f64, TiledError> {
+    value[name]
+        .as_f64()
+        .ok_or_else(|| TiledError::Parse(format!("missing number `{}`", name)))
+}
+
+fn json_property(value: &Value, name: &str) -> Option<String> {
+    let property = value["properties"]
+        .as_array()?
+        .iter()
+        .find(|property| property["name"].as_str() == Some(name))?;
+
+    match &property["value"] {
+        Value::String(value) => Some(value.clone()),
+        value => Some(value.to_string()),
+    }
+}
+
+fn file_stem(path: &str) -> String {
+    Path::new(path)
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const WALLED: &str = include_str!("../fixtures/walled.tmx");
+
+    fn tiles() -> TileNames {
+        TileNames::from_firmware().unwrap()
+    }
+
+    fn tmx(text: &str) -> Result<LevelData, Vec<TiledError>> {
+        import(Path::new("walled.tmx"), text, &tiles())
+    }
+
+    /// A map of empty tiles with one tileset for sheet 0 and the given objects
+    fn json(data: &[u32], objects: &str) -> Result<LevelData, Vec<TiledError>> {
+        let data: Vec<String> = data.iter().map(u32::to_string).collect();
+        let text = format!(
+            r#"{{
+                "width": 15, "height": 10, "tilewidth": 32, "tileheight": 32,
+                "tilesets": [{{ "firstgid": 1, "name": "tiles0" }}],
+                "layers": [
+                    {{ "type": "tilelayer", "data": [{}] }},
+                    {{ "type": "objectgroup", "objects": [{}] }}
+                ]
+            }}"#,
+            data.join(","),
+            objects
+        );
+        import(Path::new("plain.json"), &text, &tiles())
+    }
+
+    const ITEMS: &str = r#"
+        { "name": "wizard", "x": 32, "y": 32, "width": 32, "height": 32 },
+        { "type": "exit", "x": 96, "y": 32, "width": 32, "height": 32 }"#;
+
+    #[test]
+    fn fixture_map() {
+        let level = tmx(WALLED).unwrap();
+        assert_eq!(level.name(), "Walled");
+        assert_eq!(level.theme, Some(2));
+
+        let wall = tiles().get("BRICK_WALL1").unwrap();
+        assert_eq!(level.tiles[0][0], wall);
+        assert_eq!(level.tiles[HEIGHT - 1][WIDTH - 1], wall);
+        assert_eq!(level.tiles[1][1], 0);
+
+        let items: Vec<(ItemKind, u8, u8)> = level
+            .items()
+            .map(|item| (item.kind, item.x, item.y))
+            .collect();
+        assert_eq!(
+            items,
+            [
+                (ItemKind::Wizard, 1, 2),
+                (ItemKind::Exit, 13, 8),
+                (ItemKind::Spider, 6, 4)
+            ]
+        );
+    }
+
+    #[test]
+    fn json_map() {
+        let mut data = [0; WIDTH * HEIGHT];
+        data[WIDTH + 3] = 3;
+        let level = json(&data, ITEMS).unwrap();
+        assert_eq!(level.name(), "plain");
+        assert_eq!(level.tiles[1][3], tiles().get("GROUND1").unwrap());
+    }
+
+    #[test]
+    fn unknown_gid() {
+        // Before the first tileset
+        let walled = WALLED.replace(r#"firstgid="1""#, r#"firstgid="2""#);
+        let walled = walled.replacen("0,", "1,", 1);
+        let errors = tmx(&walled).unwrap_err();
+        assert!(matches!(errors[..], [TiledError::UnknownGid(1)]));
+
+        // Past the last tile of a sheet, and on to an id that is not a byte
+        let mut data = [0; WIDTH * HEIGHT];
+        data[0] = 33;
+        data[1] = 0x0fff_ffff;
+        let errors = json(&data, ITEMS).unwrap_err();
+        assert!(matches!(
+            errors[..],
+            [
+                TiledError::UnknownTile(0, 32),
+                TiledError::UnknownTile(0, 0x0fff_fffe)
+            ]
+        ));
+    }
+
+    #[test]
+    fn unknown_object() {
+        let objects = format!(r#"{}, {{ "name": "dragon", "x": 0, "y": 0 }}"#, ITEMS);
+        let errors = json(&[0; WIDTH * HEIGHT], &objects).unwrap_err();
+        assert!(matches!(&errors[..], [TiledError::UnknownObject(name)] if name == "dragon"));
+    }
+
+    #[test]
+    fn layer_size() {
+        let errors = json(&[0; WIDTH * HEIGHT - 1], ITEMS).unwrap_err();
+        assert!(matches!(errors[..], [TiledError::BadLayerSize(len)] if len == WIDTH * HEIGHT - 1));
+
+        let errors = json(&[0; WIDTH * HEIGHT + WIDTH], ITEMS).unwrap_err();
+        assert!(
+            matches!(errors[..], [TiledError::BadLayerSize(len)] if len == WIDTH * (HEIGHT + 1))
+        );
+    }
+}
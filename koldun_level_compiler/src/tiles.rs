@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt;
+
+const TILES_RS: &str = include_str!("../../koldun/src/game/tiles.rs");
+
+pub const TILE_SIZE: u32 = 32;
+
+/// Tile names and ids as declared in the firmware's `tiles.rs`,
+/// e.g. `pub const BRICK_WALL1: (usize, usize) = (1, 4);` becomes `BRICK_WALL1 => 36`.
+pub struct TileNames(HashMap<String, u8>);
+
+/// A tile whose id doesn't fit the byte a level stores it in
+#[derive(Debug, PartialEq, Eq)]
+pub struct BadTileId {
+    pub name: String,
+    pub sheet: usize,
+    pub index: usize,
+}
+
+impl fmt::Display for BadTileId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tile `{}` at ({}, {}) has an id above {}",
+            self.name,
+            self.sheet,
+            self.index,
+            u8::MAX
+        )
+    }
+}
+
+impl TileNames {
+    pub fn from_firmware() -> Result<Self, BadTileId> {
+        TileNames::parse(TILES_RS)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, BadTileId> {
+        let mut names = HashMap::new();
+        for line in source.lines() {
+            if let Some((name, id)) = parse_const(line) {
+                names.insert(name.to_string(), id?);
+            }
+        }
+        Ok(TileNames(names))
+    }
+
+    pub fn get(&self, name: &str) -> Option<u8> {
+        self.0.get(name).copied()
+    }
+
+    pub fn name(&self, id: u8) -> Option<&str> {
+        self.0
+            .iter()
+            .find(|(_, tile_id)| **tile_id == id)
+            .map(|(name, _)| name.as_str())
+    }
+}
+
+fn parse_const(line: &str) -> Option<(&str, Result<u8, BadTileId>)> {
+    let rest = line.trim().strip_prefix("pub const ")?;
+    let (name, rest) = rest.split_once(':')?;
+    let (ty, value) = rest.split_once('=')?;
+    if ty.trim() != "(usize, usize)" {
+        return None;
+    }
+
+    let value = value.trim().strip_prefix('(')?.strip_suffix(");")?;
+    let (sheet, index) = value.split_once(',')?;
+    let sheet: usize = sheet.trim().parse().ok()?;
+    let index: usize = index.trim().parse().ok()?;
+    let name = name.trim();
+    let id = sheet
+        .checked_mul(32)
+        .and_then(|id| id.checked_add(index))
+        .and_then(|id| u8::try_from(id).ok())
+        .ok_or_else(|| BadTileId {
+            name: name.to_string(),
+            sheet,
+            index,
+        });
+    Some((name, id))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ids() {
+        let tiles = TileNames::parse(
+            "pub const EMPTY: (usize, usize) = (0, 0);\n\
+             pub const BRICK_WALL1: (usize, usize) = (1, 4);\n\
+             pub const TILE_SIZE_X: usize = 32;\n\
+             pub const LAST: (usize, usize) = (7, 31);",
+        )
+        .unwrap();
+        assert_eq!(tiles.get("EMPTY"), Some(0));
+        assert_eq!(tiles.get("BRICK_WALL1"), Some(36));
+        assert_eq!(tiles.get("LAST"), Some(255));
+        assert_eq!(tiles.get("TILE_SIZE_X"), None);
+        assert_eq!(tiles.name(36), Some("BRICK_WALL1"));
+    }
+
+    #[test]
+    fn id_past_a_byte() {
+        let err = TileNames::parse("pub const FAR: (usize, usize) = (8, 0);").err();
+        assert_eq!(
+            err,
+            Some(BadTileId {
+                name: "FAR".to_string(),
+                sheet: 8,
+                index: 0
+            })
+        );
+
+        let huge = format!("pub const HUGE: (usize, usize) = ({}, 1);", usize::MAX);
+        assert!(TileNames::parse(&huge).is_err());
+    }
+
+    #[test]
+    fn firmware_tiles() {
+        let tiles = TileNames::from_firmware().unwrap();
+        assert_eq!(tiles.get("BRICK_WALL1"), Some(36));
+        assert_eq!(tiles.get("WIZARD_IDLE1"), Some(64));
+    }
+}
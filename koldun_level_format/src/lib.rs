@@ -0,0 +1,300 @@
+#![no_std]
+
+//! Binary level format shared by the firmware and the host tools.
+//!
+//! Every level occupies one `LEVEL_SIZE` slot, slots are stored back to back:
+//!
+//! | offset        | size           | field                                  |
+//! |---------------|----------------|----------------------------------------|
+//! | 0             | 4              | magic `KLVL`                           |
+//! | 4             | 1              | format version                         |
+//! | 5             | 1              | width, always `WIDTH`                  |
+//! | 6             | 1              | height, always `HEIGHT`                |
+//! | 7             | 1              | number of item placements              |
+//! | 8             | `NAME_LEN`     | level name, ASCII, zero padded         |
+//! | `HEADER_SIZE` | `WIDTH*HEIGHT` | tile ids (`sheet * 32 + index`), rows  |
+//! | ...           | 3 per item     | placements: kind, x, y                 |
+//! | `THEME_OFFSET`| 1              | theme, 0 or index in `THEMES` + 1      |
+//!
+//! The rest of the slot is zero filled.
+
+use core::fmt;
+
+pub const MAGIC: [u8; 4] = *b"KLVL";
+/// Format version, slots of any other version are rejected:
+///
+/// 1. Tiles, wizard and exit
+/// 2. Spider and door items
+/// 3. Theme byte
+pub const VERSION: u8 = 3;
+
+pub const WIDTH: usize = 15;
+pub const HEIGHT: usize = 10;
+pub const NAME_LEN: usize = 16;
+pub const MAX_ITEMS: usize = 16;
+
+pub const HEADER_SIZE: usize = 8 + NAME_LEN;
+pub const PLACEMENT_SIZE: usize = 3;
+pub const LEVEL_SIZE: usize = 256;
+
+const TILES_START: usize = HEADER_SIZE;
+const ITEMS_START: usize = TILES_START + WIDTH * HEIGHT;
+
+pub const THEME_OFFSET: usize = ITEMS_START + MAX_ITEMS * PLACEMENT_SIZE;
+
+const _: () = assert!(THEME_OFFSET < LEVEL_SIZE);
+
+/// Themes a level can be drawn in, in the order of the firmware's `Theme`
+pub const THEMES: [&str; 3] = ["dungeon", "forest", "ice"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(u8)]
+pub enum ItemKind {
+    Wizard = 1,
+    Exit = 2,
+    Spider = 3,
+    Door = 4,
+}
+
+impl ItemKind {
+    pub const ALL: [ItemKind; 4] = [
+        ItemKind::Wizard,
+        ItemKind::Exit,
+        ItemKind::Spider,
+        ItemKind::Door,
+    ];
+
+    /// Name used for the item by level sources and map editors
+    pub fn name(&self) -> &'static str {
+        match self {
+            ItemKind::Wizard => "wizard",
+            ItemKind::Exit => "exit",
+            ItemKind::Spider => "spider",
+            ItemKind::Door => "door",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<ItemKind> {
+        ItemKind::ALL
+            .into_iter()
+            .find(|kind| kind.name().eq_ignore_ascii_case(name))
+    }
+}
+
+impl TryFrom<u8> for ItemKind {
+    type Error = FormatError;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            1 => Ok(ItemKind::Wizard),
+            2 => Ok(ItemKind::Exit),
+            3 => Ok(ItemKind::Spider),
+            4 => Ok(ItemKind::Door),
+            _ => Err(FormatError::UnknownItem(value)),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Placement {
+    pub kind: ItemKind,
+    pub x: u8,
+    pub y: u8,
+}
+
+impl Placement {
+    pub fn new(kind: ItemKind, x: u8, y: u8) -> Self {
+        Placement { kind, x, y }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LevelData {
+    pub name: [u8; NAME_LEN],
+    pub tiles: [[u8; WIDTH]; HEIGHT],
+    items: [Option<Placement>; MAX_ITEMS],
+    /// Index in `THEMES`, `None` leaves the theme to the player
+    pub theme: Option<u8>,
+}
+
+impl LevelData {
+    pub fn new() -> Self {
+        LevelData {
+            name: [0; NAME_LEN],
+            tiles: [[0; WIDTH]; HEIGHT],
+            items: [None; MAX_ITEMS],
+            theme: None,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        let len = self.name.iter().position(|b| *b == 0).unwrap_or(NAME_LEN);
+        core::str::from_utf8(&self.name[..len]).unwrap_or("")
+    }
+
+    pub fn set_name(&mut self, name: &str) -> Result<(), FormatError> {
+        if name.len() > NAME_LEN || !name.is_ascii() {
+            return Err(FormatError::BadName);
+        }
+        self.name = [0; NAME_LEN];
+        self.name[..name.len()].copy_from_slice(name.as_bytes());
+        Ok(())
+    }
+
+    pub fn items(&self) -> impl Iterator<Item = &Placement> {
+        self.items.iter().flatten()
+    }
+
+    pub fn push_item(&mut self, placement: Placement) -> Result<(), FormatError> {
+        match self.items.iter_mut().find(|item| item.is_none()) {
+            Some(slot) => {
+                *slot = Some(placement);
+                Ok(())
+            }
+            None => Err(FormatError::TooManyItems),
+        }
+    }
+
+    /// Checks the invariants every playable level must hold.
+    pub fn validate(&self) -> Result<(), FormatError> {
+        let mut wizards = 0;
+        let mut exits = 0;
+        for item in self.items() {
+            if item.x as usize >= WIDTH || item.y as usize >= HEIGHT {
+                return Err(FormatError::OutOfBounds(item.x, item.y));
+            }
+            match item.kind {
+                ItemKind::Wizard => wizards += 1,
+                ItemKind::Exit => exits += 1,
+                ItemKind::Spider | ItemKind::Door => (),
+            }
+        }
+
+        match (wizards, exits) {
+            (0, _) => Err(FormatError::MissingWizard),
+            (1, 0) => Err(FormatError::MissingExit),
+            (1, _) => Ok(()),
+            _ => Err(FormatError::MultipleWizards),
+        }
+    }
+
+    pub fn decode(data: &[u8]) -> Result<Self, FormatError> {
+        if data.len() < LEVEL_SIZE {
+            return Err(FormatError::TooShort(data.len()));
+        }
+        if data[0..4] != MAGIC {
+            return Err(FormatError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(FormatError::UnsupportedVersion(data[4]));
+        }
+        if data[5] as usize != WIDTH || data[6] as usize != HEIGHT {
+            return Err(FormatError::BadDimensions(data[5], data[6]));
+        }
+
+        let items_len = data[7] as usize;
+        if items_len > MAX_ITEMS {
+            return Err(FormatError::TooManyItems);
+        }
+
+        let mut level = LevelData::new();
+        level.name.copy_from_slice(&data[8..HEADER_SIZE]);
+
+        for (y, row) in level.tiles.iter_mut().enumerate() {
+            let start = TILES_START + y * WIDTH;
+            row.copy_from_slice(&data[start..start + WIDTH]);
+        }
+
+        for i in 0..items_len {
+            let start = ITEMS_START + i * PLACEMENT_SIZE;
+            let kind = ItemKind::try_from(data[start])?;
+            level.push_item(Placement::new(kind, data[start + 1], data[start + 2]))?;
+        }
+
+        // A theme this build doesn't know is left to the player, the level still plays
+        level.theme = match data[THEME_OFFSET] {
+            0 => None,
+            theme => Some(theme - 1).filter(|theme| (*theme as usize) < THEMES.len()),
+        };
+
+        level.validate()?;
+        Ok(level)
+    }
+
+    pub fn encode(&self) -> [u8; LEVEL_SIZE] {
+        let mut data = [0u8; LEVEL_SIZE];
+        data[0..4].copy_from_slice(&MAGIC);
+        data[4] = VERSION;
+        data[5] = WIDTH as u8;
+        data[6] = HEIGHT as u8;
+        data[8..HEADER_SIZE].copy_from_slice(&self.name);
+
+        for (y, row) in self.tiles.iter().enumerate() {
+            let start = TILES_START + y * WIDTH;
+            data[start..start + WIDTH].copy_from_slice(row);
+        }
+
+        let mut items_len = 0;
+        for (i, item) in self.items().enumerate() {
+            let start = ITEMS_START + i * PLACEMENT_SIZE;
+            data[start] = item.kind as u8;
+            data[start + 1] = item.x;
+            data[start + 2] = item.y;
+            items_len += 1;
+        }
+        data[7] = items_len;
+        data[THEME_OFFSET] = self.theme.map_or(0, |theme| theme + 1);
+
+        data
+    }
+}
+
+impl Default for LevelData {
+    fn default() -> Self {
+        LevelData::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FormatError {
+    TooShort(usize),
+    BadMagic,
+    UnsupportedVersion(u8),
+    BadDimensions(u8, u8),
+    BadName,
+    UnknownItem(u8),
+    /// A tile id the firmware has no tile for
+    UnknownTile(u8),
+    TooManyItems,
+    OutOfBounds(u8, u8),
+    MissingWizard,
+    MultipleWizards,
+    MissingExit,
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::TooShort(len) => write!(f, "Level data too short: {} bytes", len),
+            FormatError::BadMagic => write!(f, "Not a level"),
+            FormatError::UnsupportedVersion(version) => {
+                write!(f, "Unsupported level version {}", version)
+            }
+            FormatError::BadDimensions(width, height) => {
+                write!(
+                    f,
+                    "Level must be {}x{}, got {}x{}",
+                    WIDTH, HEIGHT, width, height
+                )
+            }
+            FormatError::BadName => write!(f, "Level name must be ASCII, {} chars max", NAME_LEN),
+            FormatError::UnknownItem(kind) => write!(f, "Unknown item kind {}", kind),
+            FormatError::UnknownTile(id) => write!(f, "Unknown tile {}", id),
+            FormatError::TooManyItems => write!(f, "More than {} items", MAX_ITEMS),
+            FormatError::OutOfBounds(x, y) => write!(f, "Item at ({}, {}) is out of bounds", x, y),
+            FormatError::MissingWizard => write!(f, "Level has no wizard"),
+            FormatError::MultipleWizards => write!(f, "Level has more than one wizard"),
+            FormatError::MissingExit => write!(f, "Level has no exit"),
+        }
+    }
+}
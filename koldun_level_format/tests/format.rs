@@ -0,0 +1,141 @@
+//! Levels survive an encode and decode, broken slots are rejected.
+
+use koldun_level_format::{
+    FormatError, ItemKind, LevelData, Placement, HEADER_SIZE, HEIGHT, LEVEL_SIZE, MAGIC, MAX_ITEMS,
+    VERSION, WIDTH,
+};
+
+/// Offset of the first placement in a slot
+const ITEMS_START: usize = HEADER_SIZE + WIDTH * HEIGHT;
+
+fn level() -> LevelData {
+    let mut level = LevelData::new();
+    level.set_name("Ruins").unwrap();
+    for (y, row) in level.tiles.iter_mut().enumerate() {
+        for (x, tile) in row.iter_mut().enumerate() {
+            *tile = (x + y) as u8;
+        }
+    }
+    level
+        .push_item(Placement::new(ItemKind::Wizard, 10, 5))
+        .unwrap();
+    level
+        .push_item(Placement::new(ItemKind::Exit, 10, 7))
+        .unwrap();
+    level
+        .push_item(Placement::new(ItemKind::Door, 0, 0))
+        .unwrap();
+    level
+        .push_item(Placement::new(
+            ItemKind::Spider,
+            WIDTH as u8 - 1,
+            HEIGHT as u8 - 1,
+        ))
+        .unwrap();
+    level
+}
+
+#[test]
+fn round_trip() {
+    let level = level();
+    let data = level.encode();
+    assert_eq!(data.len(), LEVEL_SIZE);
+    assert_eq!(data[0..4], MAGIC);
+    assert_eq!(data[4], VERSION);
+    assert_eq!(LevelData::decode(&data), Ok(level.clone()));
+
+    let decoded = LevelData::decode(&data).unwrap();
+    assert_eq!(decoded.name(), "Ruins");
+    assert_eq!(decoded.items().count(), 4);
+}
+
+#[test]
+fn slots_back_to_back() {
+    let mut second = level();
+    second.set_name("Second").unwrap();
+    let blob = [level().encode(), second.encode()].concat();
+
+    let slot = |index: usize| LevelData::decode(&blob[index * LEVEL_SIZE..]);
+    assert_eq!(slot(0).unwrap().name(), "Ruins");
+    assert_eq!(slot(1).unwrap().name(), "Second");
+}
+
+#[test]
+fn truncated_slot() {
+    let data = level().encode();
+    assert_eq!(
+        LevelData::decode(&data[..LEVEL_SIZE - 1]),
+        Err(FormatError::TooShort(LEVEL_SIZE - 1))
+    );
+    assert_eq!(LevelData::decode(&[]), Err(FormatError::TooShort(0)));
+}
+
+#[test]
+fn bad_magic() {
+    let mut data = level().encode();
+    data[0] = b'X';
+    assert_eq!(LevelData::decode(&data), Err(FormatError::BadMagic));
+
+    // Erased flash
+    assert_eq!(
+        LevelData::decode(&[0xff; LEVEL_SIZE]),
+        Err(FormatError::BadMagic)
+    );
+}
+
+#[test]
+fn bad_version() {
+    let mut data = level().encode();
+    data[4] = VERSION + 1;
+    assert_eq!(
+        LevelData::decode(&data),
+        Err(FormatError::UnsupportedVersion(VERSION + 1))
+    );
+}
+
+#[test]
+fn out_of_range_index() {
+    // Placement outside of the map
+    let mut data = level().encode();
+    data[ITEMS_START + 1] = WIDTH as u8;
+    assert_eq!(
+        LevelData::decode(&data),
+        Err(FormatError::OutOfBounds(WIDTH as u8, 5))
+    );
+
+    // Item kind past the last one
+    let mut data = level().encode();
+    data[ITEMS_START] = ItemKind::ALL.len() as u8 + 1;
+    assert_eq!(
+        LevelData::decode(&data),
+        Err(FormatError::UnknownItem(ItemKind::ALL.len() as u8 + 1))
+    );
+
+    // More placements than a slot holds
+    let mut data = level().encode();
+    data[7] = MAX_ITEMS as u8 + 1;
+    assert_eq!(LevelData::decode(&data), Err(FormatError::TooManyItems));
+}
+
+#[test]
+fn validation() {
+    let mut level = LevelData::new();
+    assert_eq!(level.validate(), Err(FormatError::MissingWizard));
+    level
+        .push_item(Placement::new(ItemKind::Wizard, 1, 1))
+        .unwrap();
+    assert_eq!(level.validate(), Err(FormatError::MissingExit));
+    level
+        .push_item(Placement::new(ItemKind::Exit, 2, 1))
+        .unwrap();
+    assert_eq!(level.validate(), Ok(()));
+    level
+        .push_item(Placement::new(ItemKind::Wizard, 3, 1))
+        .unwrap();
+    assert_eq!(level.validate(), Err(FormatError::MultipleWizards));
+
+    assert_eq!(
+        level.set_name("A name far too long"),
+        Err(FormatError::BadName)
+    );
+}
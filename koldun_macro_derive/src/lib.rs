@@ -1,58 +1,605 @@
+use koldun_level_format::{ItemKind, LevelData, Placement, HEIGHT, THEMES, WIDTH};
 use proc_macro::TokenStream;
 use quote::{format_ident, quote};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
 use syn::parse::{Parse, ParseStream, Result};
 use syn::punctuated::Punctuated;
-use syn::{parse_macro_input, Ident, ItemStruct, Token};
-use to_snake_case::ToSnakeCase;
+use syn::{braced, bracketed, parenthesized};
+use syn::{parse_macro_input, Ident, ItemStruct, LitBool, LitChar, LitInt, LitStr, Token};
 
 struct Args {
-    variants: Vec<Ident>,
+    /// Bits per pixel of each sheet, in `TILEMAPS` order
+    depths: Vec<LitInt>,
+    variants: Vec<TileEntry>,
 }
 
 impl Parse for Args {
     fn parse(input: ParseStream) -> Result<Self> {
-        let variants = Punctuated::<Ident, Token![,]>::parse_terminated(input)?;
+        let mut depths = Vec::new();
+        if input.peek(Ident) && input.peek2(Token![=]) {
+            let key: Ident = input.parse()?;
+            if key != "sheets" {
+                return Err(syn::Error::new(key.span(), "expected `sheets = [...]`"));
+            }
+            input.parse::<Token![=]>()?;
+            let content;
+            bracketed!(content in input);
+            for depth in Punctuated::<LitInt, Token![,]>::parse_terminated(&content)? {
+                if !matches!(depth.base10_parse::<u8>()?, 1 | 2 | 4) {
+                    return Err(syn::Error::new(
+                        depth.span(),
+                        "a sheet has 1, 2 or 4 bits per pixel",
+                    ));
+                }
+                depths.push(depth);
+            }
+            input.parse::<Token![,]>()?;
+        }
+
+        let variants = Punctuated::<TileEntry, Token![,]>::parse_terminated(input)?;
         Ok(Args {
+            depths,
             variants: variants.into_iter().collect(),
         })
     }
 }
 
+/// A tile constant with the `TileMeta` fields that differ from `TileMeta::FLOOR`
+struct TileEntry {
+    name: Ident,
+    meta: Vec<proc_macro2::TokenStream>,
+}
+
+impl Parse for TileEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let name = input.parse()?;
+        let mut meta = Vec::new();
+        if input.peek(syn::token::Brace) {
+            let content;
+            braced!(content in input);
+            while !content.is_empty() {
+                meta.push(parse_meta_field(&content)?);
+                if !content.is_empty() {
+                    content.parse::<Token![,]>()?;
+                }
+            }
+        }
+        Ok(TileEntry { name, meta })
+    }
+}
+
+fn parse_meta_field(input: ParseStream) -> Result<proc_macro2::TokenStream> {
+    let key: Ident = input.parse()?;
+    input.parse::<Token![=]>()?;
+    Ok(match key.to_string().as_str() {
+        "layer" => {
+            let value: LitInt = input.parse()?;
+            quote! { layer: #value }
+        }
+        "walkable" | "blocks_spells" | "hazard" | "transparent" => {
+            let value: LitBool = input.parse()?;
+            quote! { #key: #value }
+        }
+        "animated_with" => {
+            let value: Ident = input.parse()?;
+            let value = format_ident!("{}", to_camel_case(&value.to_string()));
+            quote! { animated_with: Some(TileId::#value) }
+        }
+        "default_fg" | "default_bg" => {
+            let value: Ident = input.parse()?;
+            quote! { #key: Ink::#value }
+        }
+        _ => {
+            return Err(syn::Error::new(
+                key.span(),
+                "expected `layer`, `walkable`, `blocks_spells`, `hazard`, `transparent`, \
+                 `animated_with`, `default_fg` or `default_bg`",
+            ))
+        }
+    })
+}
+
+/// Generates a `TileId` enum of the listed tile constants, `BRICK_WALL1` becoming
+/// `TileId::BrickWall1`, with renderers, names and the metadata declared here.
+///
+/// ```ignore
+/// #[render_tiles(
+///     sheets = [1, 1, 4],
+///     EMPTY,
+///     BRICK_WALL1 { layer = 1, walkable = false, blocks_spells = true },
+///     WEB { hazard = true },
+///     WIZARD_IDLE1 { layer = 1, transparent = true, animated_with = WIZARD_IDLE2 },
+/// )]
+/// pub struct Tile {}
+/// ```
+///
+/// `sheets` gives the bits per pixel of each sheet in `TILEMAPS`: 1 for two-colour tiles,
+/// 2 or 4 for tiles indexing a `TilePalette`. Sheets left out are 1bpp.
+/// Fields not given are taken from `TileMeta::FLOOR`. `TILEMAPS`, `Rgb565`, `TileMeta`, `Ink`
+/// and `UnknownTile` must be in scope, the annotated struct provides
+/// `render(bits, depth, colors)`.
 #[proc_macro_attribute]
 pub fn render_tiles(attr: TokenStream, item: TokenStream) -> TokenStream {
     let item = parse_macro_input!(item as ItemStruct);
     let args = parse_macro_input!(attr as Args);
     let name = &item.ident;
+    let depths = &args.depths;
+
+    let mut variants = quote! {};
+    let mut all = quote! {};
+    let mut names = quote! {};
+    let mut sources = quote! {};
+    let mut meta = quote! {};
+
+    for TileEntry {
+        name: constant,
+        meta: fields,
+    } in &args.variants
+    {
+        let variant = format_ident!("{}", to_camel_case(&constant.to_string()));
+        let constant_name = constant.to_string();
+
+        variants.extend(quote! { #variant = (#constant.0 * 32 + #constant.1) as u16, });
+        all.extend(quote! { TileId::#variant, });
+        names.extend(quote! { TileId::#variant => #constant_name, });
+        sources.extend(quote! { TileId::#variant => #constant, });
+        meta.extend(quote! {
+            TileId::#variant => TileMeta { #(#fields,)* ..TileMeta::FLOOR },
+        });
+    }
+
+    let gen = quote! {
+        #item
+
+        /// Id of a tile: `sheet * 32 + index` in `TILEMAPS`
+        #[derive(Clone, Copy, PartialEq, Eq, Hash)]
+        #[repr(u16)]
+        pub enum TileId {
+            #variants
+        }
+
+        impl TileId {
+            pub const ALL: &'static [TileId] = &[#all];
+
+            /// Name of the tile constant
+            pub const fn name(&self) -> &'static str {
+                match self {
+                    #names
+                }
+            }
+
+            /// Bits per pixel of each sheet in `TILEMAPS`
+            pub const SHEET_DEPTHS: &'static [usize] = &[#(#depths),*];
+
+            /// Sheet and index of the tile in it
+            const fn source(&self) -> (usize, usize) {
+                match self {
+                    #sources
+                }
+            }
+
+            /// Bits per pixel, 1 for two-colour tiles
+            pub const fn depth(&self) -> usize {
+                let (sheet, _) = self.source();
+                match sheet < Self::SHEET_DEPTHS.len() {
+                    true => Self::SHEET_DEPTHS[sheet],
+                    false => 1,
+                }
+            }
+
+            /// Source of the tile in `TILEMAPS`, `depth` bits per pixel
+            pub fn bits(&self) -> &'static [u8] {
+                let (sheet, index) = self.source();
+                let size = 32 * 32 * self.depth() / 8;
+                &TILEMAPS[sheet][index * size..(index + 1) * size]
+            }
 
-    let mut parse_methods = quote! {};
+            /// Renders the tile, each pixel in the colour its value indexes
+            pub fn render(&self, colors: &[Rgb565]) -> [u8; 32 * 32 * 2] {
+                #name::render(self.bits(), self.depth(), colors)
+            }
+
+            pub const fn meta(&self) -> TileMeta {
+                match self {
+                    #meta
+                }
+            }
+        }
+
+        impl TryFrom<u16> for TileId {
+            type Error = UnknownTile;
+
+            fn try_from(id: u16) -> core::result::Result<Self, Self::Error> {
+                TileId::ALL
+                    .iter()
+                    .find(|tile| **tile as u16 == id)
+                    .copied()
+                    .ok_or(UnknownTile(id))
+            }
+        }
+    };
 
-    for variant in &args.variants {
-        let fn_name = format_ident!("{}", variant.to_string().to_snake_case());
-        let fn_id = format_ident!("{}_id", variant.to_string().to_snake_case());
+    gen.into()
+}
 
-        let parse_method = quote! {
-            pub fn #fn_name(fg: Rgb565, bg: Rgb565) -> [u8; 32 * 32 * 2] {
-                let start = #variant.1 * 128;
-                let finish = start + 128;
-                let mut bits = [0u8; 128];
-                bits.copy_from_slice(&TILEMAPS[#variant.0][start..finish]);
-                #name::render(&bits, fg, bg)
+/// `BRICK_WALL1` -> `BrickWall1`
+fn to_camel_case(name: &str) -> String {
+    name.split('_')
+        .map(|word| {
+            let mut chars = word.chars();
+            match chars.next() {
+                Some(first) => {
+                    first.to_ascii_uppercase().to_string() + &chars.as_str().to_lowercase()
+                }
+                None => String::new(),
             }
+        })
+        .collect()
+}
+
+struct LegendEntry {
+    glyph: LitChar,
+    value: Ident,
+    under: Option<Ident>,
+}
 
-            pub fn #fn_id() -> usize{
-                #variant.0 * 32 + #variant.1
+impl Parse for LegendEntry {
+    fn parse(input: ParseStream) -> Result<Self> {
+        let glyph = input.parse()?;
+        input.parse::<Token![=>]>()?;
+        let value = input.parse()?;
+        let under = match input.peek(syn::token::Paren) {
+            true => {
+                let content;
+                parenthesized!(content in input);
+                Some(content.parse()?)
             }
+            false => None,
         };
-        parse_methods.extend(parse_method);
+        Ok(LegendEntry {
+            glyph,
+            value,
+            under,
+        })
     }
+}
 
-    let gen = quote! {
-        #item
+struct LevelSource {
+    name: Option<LitStr>,
+    theme: Option<Ident>,
+    /// Tile drawn under items that don't name one
+    floor: Option<Ident>,
+    legend: Vec<LegendEntry>,
+    rows_span: proc_macro2::Span,
+    rows: Vec<LitStr>,
+    /// Level source file the level was read from
+    file: Option<String>,
+}
+
+impl Parse for LevelSource {
+    fn parse(input: ParseStream) -> Result<Self> {
+        if input.peek(LitStr) {
+            let path: LitStr = input.parse()?;
+            return read_source(&path);
+        }
+
+        let mut name = None;
+        let mut theme = None;
+        let mut floor = None;
+        let mut legend = Vec::new();
+        let mut rows = Vec::new();
+        let mut rows_span = input.span();
+
+        while !input.is_empty() {
+            let key: Ident = input.parse()?;
+            match key.to_string().as_str() {
+                "name" => {
+                    input.parse::<Token![=]>()?;
+                    name = Some(input.parse()?);
+                }
+                "theme" => {
+                    input.parse::<Token![=]>()?;
+                    theme = Some(input.parse()?);
+                }
+                "floor" => {
+                    input.parse::<Token![=]>()?;
+                    floor = Some(input.parse()?);
+                }
+                "legend" => {
+                    let content;
+                    braced!(content in input);
+                    legend = Punctuated::<LegendEntry, Token![,]>::parse_terminated(&content)?
+                        .into_iter()
+                        .collect();
+                }
+                "rows" => {
+                    let content;
+                    bracketed!(content in input);
+                    rows_span = key.span();
+                    rows = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?
+                        .into_iter()
+                        .collect();
+                }
+                _ => {
+                    return Err(syn::Error::new(
+                        key.span(),
+                        "expected `name`, `theme`, `floor`, `legend` or `rows`",
+                    ))
+                }
+            }
 
-        impl #name{
-            #parse_methods
+            if !input.is_empty() {
+                input.parse::<Token![,]>()?;
+            }
         }
+
+        Ok(LevelSource {
+            name,
+            theme,
+            floor,
+            legend,
+            rows_span,
+            rows,
+            file: None,
+        })
+    }
+}
+
+/// Reads a level source file, the one the level compiler takes, relative to the crate root
+fn read_source(path: &LitStr) -> Result<LevelSource> {
+    let span = path.span();
+    let root = std::env::var("CARGO_MANIFEST_DIR").unwrap_or_default();
+    let file = Path::new(&root).join(path.value());
+    let text = fs::read_to_string(&file)
+        .map_err(|err| syn::Error::new(span, format!("can't read {}: {}", file.display(), err)))?;
+
+    let ident = |name: &str, line_no: usize| {
+        syn::parse_str::<Ident>(name)
+            .map(|mut ident| {
+                ident.set_span(span);
+                ident
+            })
+            .map_err(|_| {
+                syn::Error::new(
+                    span,
+                    format!("{}:{}: `{}` is not a name", path.value(), line_no, name),
+                )
+            })
+    };
+    let error = |line_no: usize, message: &str| {
+        syn::Error::new(span, format!("{}:{}: {}", path.value(), line_no, message))
     };
 
-    gen.into()
+    let mut source = LevelSource {
+        name: None,
+        theme: None,
+        floor: None,
+        legend: Vec::new(),
+        rows_span: span,
+        rows: Vec::new(),
+        file: Some(file.to_string_lossy().into_owned()),
+    };
+    let mut section = "";
+
+    for (i, line) in text.lines().enumerate() {
+        let line_no = i + 1;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") {
+            continue;
+        }
+
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            if name != "legend" && name != "map" {
+                return Err(error(line_no, &format!("unknown section `[{}]`", name)));
+            }
+            section = name;
+            continue;
+        }
+
+        match section {
+            "" => {
+                let (key, value) = line
+                    .split_once('=')
+                    .ok_or_else(|| error(line_no, "expected `key = value`"))?;
+                let value = value.trim();
+                match key.trim() {
+                    "name" => source.name = Some(LitStr::new(value, span)),
+                    "theme" => source.theme = Some(ident(value, line_no)?),
+                    "floor" => source.floor = Some(ident(value, line_no)?),
+                    key => return Err(error(line_no, &format!("unknown key `{}`", key))),
+                }
+            }
+
+            "legend" => {
+                let mut chars = line.chars();
+                let glyph = chars.next().unwrap();
+                let value = chars
+                    .as_str()
+                    .trim()
+                    .strip_prefix('=')
+                    .ok_or_else(|| error(line_no, "expected `glyph = value`"))?;
+                let mut words = value.split_whitespace();
+                let value = words.next().unwrap_or_default();
+                source.legend.push(LegendEntry {
+                    glyph: LitChar::new(glyph, span),
+                    value: ident(value, line_no)?,
+                    under: words
+                        .next()
+                        .map(|under| ident(under, line_no))
+                        .transpose()?,
+                });
+            }
+
+            _ => source.rows.push(LitStr::new(line, span)),
+        }
+    }
+    Ok(source)
+}
+
+enum Glyph {
+    Tile(Ident),
+    Item(ItemKind, Option<Ident>),
+}
+
+/// Builds a `BuiltinLevel`, the `Grid` of a level and its item placements, from an ASCII map.
+///
+/// ```ignore
+/// let level = level! {
+///     name = "Ruins",
+///     theme = forest,
+///     floor = EMPTY,
+///     legend {
+///         '.' => EMPTY,
+///         '#' => BRICK_WALL1,
+///         'W' => wizard,
+///         'E' => exit(GROUND1),
+///     },
+///     rows [
+///         "..#....",
+///         ...
+///     ]
+/// };
+///
+/// let level = level!("resources/levels/src/01_ruins.lvl");
+/// ```
+///
+/// Upper case legend values are tile constants from `tiles.rs`, each one must be a `TileId`
+/// variant. Lower case ones are items, drawn over `floor` (`EMPTY` if not given) unless a
+/// tile is given in parentheses. `theme` is optional, without it the level takes the
+/// player's theme. A level can also be read from a source file for the level compiler,
+/// given relative to the crate root. `BuiltinLevel`, `Grid` and `TileId` must be in scope.
+#[proc_macro]
+pub fn level(input: TokenStream) -> TokenStream {
+    let source = parse_macro_input!(input as LevelSource);
+    match expand_level(source) {
+        Ok(tokens) => tokens.into(),
+        Err(err) => err.to_compile_error().into(),
+    }
+}
+
+fn expand_level(source: LevelSource) -> Result<proc_macro2::TokenStream> {
+    let mut legend: HashMap<char, Glyph> = HashMap::new();
+    for entry in source.legend {
+        let value = entry.value.to_string();
+        let glyph = match value.chars().any(|c| c.is_ascii_lowercase()) {
+            true => {
+                let kind = ItemKind::from_name(&value).ok_or_else(|| {
+                    syn::Error::new(entry.value.span(), format!("unknown item `{}`", value))
+                })?;
+                Glyph::Item(kind, entry.under)
+            }
+            false => Glyph::Tile(entry.value),
+        };
+
+        if legend.insert(entry.glyph.value(), glyph).is_some() {
+            return Err(syn::Error::new(
+                entry.glyph.span(),
+                format!("glyph `{}` defined twice", entry.glyph.value()),
+            ));
+        }
+    }
+
+    if source.rows.len() != HEIGHT {
+        return Err(syn::Error::new(
+            source.rows_span,
+            format!("level has {} rows, expected {}", source.rows.len(), HEIGHT),
+        ));
+    }
+
+    let floor = source
+        .floor
+        .unwrap_or_else(|| Ident::new("EMPTY", proc_macro2::Span::call_site()));
+    let mut data = LevelData::new();
+    let mut rows = quote! {};
+
+    for (y, row) in source.rows.iter().enumerate() {
+        let glyphs: Vec<char> = row.value().chars().collect();
+        if glyphs.len() != WIDTH {
+            return Err(syn::Error::new(
+                row.span(),
+                format!("row is {} cells wide, expected {}", glyphs.len(), WIDTH),
+            ));
+        }
+
+        let mut cells = quote! {};
+        for (x, glyph) in glyphs.into_iter().enumerate() {
+            let tile = match legend.get(&glyph) {
+                Some(Glyph::Tile(tile)) => tile,
+                Some(Glyph::Item(kind, under)) => {
+                    data.push_item(Placement::new(*kind, x as u8, y as u8))
+                        .map_err(|err| syn::Error::new(row.span(), err.to_string()))?;
+                    under.as_ref().unwrap_or(&floor)
+                }
+                None => {
+                    return Err(syn::Error::new(
+                        row.span(),
+                        format!("glyph `{}` in column {} is not in the legend", glyph, x),
+                    ))
+                }
+            };
+            let variant = Ident::new(&to_camel_case(&tile.to_string()), tile.span());
+            cells.extend(quote! { TileId::#variant, });
+        }
+        rows.extend(quote! { [#cells], });
+    }
+
+    data.validate()
+        .map_err(|err| syn::Error::new(source.rows_span, err.to_string()))?;
+
+    let mut items = quote! {};
+    for item in data.items() {
+        let kind = format_ident!("{}", format!("{:?}", item.kind));
+        let (x, y) = (item.x, item.y);
+        items.extend(quote! {
+            ::koldun_level_format::Placement {
+                kind: ::koldun_level_format::ItemKind::#kind,
+                x: #x,
+                y: #y,
+            },
+        });
+    }
+
+    let name = match &source.name {
+        Some(name) => {
+            data.set_name(&name.value())
+                .map_err(|err| syn::Error::new(name.span(), err.to_string()))?;
+            quote! { #name }
+        }
+        None => quote! { "" },
+    };
+
+    let theme = match &source.theme {
+        Some(theme) => {
+            let index = THEMES
+                .iter()
+                .position(|name| *theme == name)
+                .ok_or_else(|| {
+                    syn::Error::new(
+                        theme.span(),
+                        format!("unknown theme `{}`, expected one of {:?}", theme, THEMES),
+                    )
+                })? as u8;
+            quote! { Some(#index) }
+        }
+        None => quote! { None },
+    };
+
+    // Rebuilds the level when its source file changes
+    let file = source.file.map(|file| {
+        quote! { const _: &[u8] = include_bytes!(#file); }
+    });
+
+    Ok(quote! {
+        {
+            #file
+            BuiltinLevel {
+                name: #name,
+                theme: #theme,
+                grid: Grid::from([#rows]),
+                items: &[#items],
+            }
+        }
+    })
 }
@@ -0,0 +1,225 @@
+use crossterm::event::{self, Event as TermEvent, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use embassy_futures::block_on;
+use koldun::framebuffer::FramebufferDisplay;
+use koldun::game::events::{Buttons, Event, States};
+use koldun::game::flash::FileFlash;
+use koldun::game::replay::Replay;
+use koldun::game::settings;
+use koldun::game::state_mashine::StateMachine;
+use std::env;
+use std::fs;
+use std::io;
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, Instant};
+use terminal::Terminal;
+
+mod terminal;
+
+const USAGE: &str =
+    "usage: koldun-sim [--save <file>] [--record <file.krec>] [--replay <file.krec>] [--tile-cache <bytes>] [levels.bin]";
+const LEVELS: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/../koldun/resources/levels/levels.bin"
+);
+
+fn main() -> ExitCode {
+    let Some(args) = Args::parse(env::args().skip(1)) else {
+        eprintln!("{}", USAGE);
+        return ExitCode::FAILURE;
+    };
+    let path = args.levels.as_deref().unwrap_or(LEVELS);
+
+    let flash = match FileFlash::open(path) {
+        Ok(flash) => flash,
+        Err(err) => {
+            eprintln!("error: {}: {}", path, err);
+            return ExitCode::FAILURE;
+        }
+    };
+    let flash = match &args.save {
+        Some(save_path) => match flash.with_storage(save_path) {
+            Ok(flash) => flash,
+            Err(err) => {
+                eprintln!("error: {}: {}", save_path, err);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => flash,
+    };
+
+    let replay = match &args.replay {
+        Some(replay_path) => {
+            let replay = fs::read(replay_path)
+                .map_err(|err| err.to_string())
+                .and_then(|data| Replay::decode(&data).map_err(|err| err.to_string()));
+            match replay {
+                Ok(replay) => Some(replay),
+                Err(err) => {
+                    eprintln!("error: {}: {}", replay_path, err);
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+        None => None,
+    };
+
+    let mut sm = StateMachine::new(FramebufferDisplay::new(), flash);
+    if let Some(budget) = args.tile_cache {
+        sm.tiles_mut().set_budget(budget);
+    }
+    if args.record.is_some() {
+        sm.record();
+    }
+    if let (Some(replay), Some(replay_path)) = (&replay, &args.replay) {
+        if let Err(err) = block_on(sm.check_replay(replay)) {
+            eprintln!("error: {}: {}", replay_path, err);
+            return ExitCode::FAILURE;
+        }
+    }
+
+    let result = Terminal::open().and_then(|mut terminal| run(&mut sm, &mut terminal, replay));
+    if let Err(err) = result {
+        eprintln!("error: {}", err);
+        return ExitCode::FAILURE;
+    }
+
+    if let Some(record_path) = &args.record {
+        let Some(recording) = sm.recorder().and_then(|recorder| recorder.encode()) else {
+            eprintln!("no level was played, {} not written", record_path);
+            return ExitCode::FAILURE;
+        };
+        if let Err(err) = fs::write(record_path, recording) {
+            eprintln!("error: {}: {}", record_path, err);
+            return ExitCode::FAILURE;
+        }
+    }
+    ExitCode::SUCCESS
+}
+
+struct Args {
+    levels: Option<String>,
+    save: Option<String>,
+    record: Option<String>,
+    replay: Option<String>,
+    tile_cache: Option<usize>,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Option<Self> {
+        let mut parsed = Args {
+            levels: None,
+            save: None,
+            record: None,
+            replay: None,
+            tile_cache: None,
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--save" => parsed.save = Some(args.next()?),
+                "--record" => parsed.record = Some(args.next()?),
+                "--replay" => parsed.replay = Some(args.next()?),
+                "--tile-cache" => parsed.tile_cache = Some(args.next()?.parse().ok()?),
+                _ if arg.starts_with('-') || parsed.levels.is_some() => return None,
+                _ => parsed.levels = Some(arg),
+            }
+        }
+        Some(parsed)
+    }
+}
+
+fn run(
+    sm: &mut StateMachine<FramebufferDisplay, FileFlash>,
+    terminal: &mut Terminal,
+    replay: Option<Replay>,
+) -> io::Result<()> {
+    let mut tick: u128 = 0;
+    let mut next_tick = Instant::now();
+
+    // Play the recording at the speed it was made, then hand over to the keyboard
+    if let Some(replay) = replay {
+        block_on(sm.enter_level(replay.level()));
+        for event in replay.events() {
+            if let Event::Tick(value) = event {
+                thread::sleep(next_tick.saturating_duration_since(Instant::now()));
+                next_tick += tick_period();
+                tick = value.wrapping_add(1);
+            }
+            block_on(sm.on_control(event));
+            terminal.draw(sm.display())?;
+
+            if quit_requested()? {
+                return Ok(());
+            }
+        }
+    }
+
+    loop {
+        if Instant::now() >= next_tick {
+            block_on(sm.on_control(Event::Tick(tick)));
+            tick = tick.wrapping_add(1);
+            next_tick += tick_period();
+            terminal.draw(sm.display())?;
+        }
+
+        if !event::poll(next_tick.saturating_duration_since(Instant::now()))? {
+            continue;
+        }
+
+        match event::read()? {
+            TermEvent::Key(key) if key.kind != KeyEventKind::Release => match key.code {
+                _ if is_quit(&key) => return Ok(()),
+                code => {
+                    // Terminals don't report releases, so every key is a full click
+                    if let Some(button) = button(code, States::Pressed) {
+                        block_on(sm.on_control(Event::Button(button)));
+                    }
+                    if let Some(button) = button(code, States::Released) {
+                        block_on(sm.on_control(Event::Button(button)));
+                    }
+                    terminal.draw(sm.display())?;
+                }
+            },
+            TermEvent::Resize(_, _) => {
+                terminal.resize()?;
+                terminal.draw(sm.display())?;
+            }
+            _ => (),
+        }
+    }
+}
+
+/// Same rate as `timer_task` on the device, set by the animation speed
+fn tick_period() -> Duration {
+    Duration::from_secs(1) / settings::current().animation_speed.tick_hz() as u32
+}
+
+fn quit_requested() -> io::Result<bool> {
+    while event::poll(Duration::ZERO)? {
+        if let TermEvent::Key(key) = event::read()? {
+            if key.kind != KeyEventKind::Release && is_quit(&key) {
+                return Ok(true);
+            }
+        }
+    }
+    Ok(false)
+}
+
+fn is_quit(key: &KeyEvent) -> bool {
+    match key.code {
+        KeyCode::Esc | KeyCode::Char('q') => true,
+        KeyCode::Char('c') => key.modifiers.contains(KeyModifiers::CONTROL),
+        _ => false,
+    }
+}
+
+fn button(code: KeyCode, state: States) -> Option<Buttons> {
+    match code {
+        KeyCode::Up => Some(Buttons::Up(state)),
+        KeyCode::Down => Some(Buttons::Down(state)),
+        KeyCode::Left => Some(Buttons::Left(state)),
+        KeyCode::Right => Some(Buttons::Right(state)),
+        KeyCode::Char(' ') => Some(Buttons::Reset(state)),
+        _ => None,
+    }
+}
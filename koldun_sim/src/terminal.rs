@@ -0,0 +1,98 @@
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::style::Print;
+use crossterm::terminal::{
+    self, disable_raw_mode, enable_raw_mode, Clear, ClearType, EnterAlternateScreen,
+    LeaveAlternateScreen,
+};
+use crossterm::{execute, queue};
+use koldun::framebuffer::{FramebufferDisplay, HEIGHT, WIDTH};
+use std::fmt::Write as _;
+use std::io::{self, BufWriter, Stdout, Write};
+
+const HELP: &str = "arrows: move  space: reset  q: quit";
+
+/// Draws the framebuffer with truecolor upper half blocks, every character
+/// cell shows two pixel rows. The frame is downscaled to fit the terminal.
+pub struct Terminal {
+    out: BufWriter<Stdout>,
+    scale: usize,
+    last: Vec<u16>,
+}
+
+impl Terminal {
+    pub fn open() -> io::Result<Self> {
+        enable_raw_mode()?;
+        let mut out = BufWriter::new(io::stdout());
+        execute!(out, EnterAlternateScreen, Hide)?;
+
+        let mut terminal = Terminal {
+            out,
+            scale: 1,
+            last: Vec::new(),
+        };
+        terminal.resize()?;
+        Ok(terminal)
+    }
+
+    /// Picks the smallest scale that fits the frame and the help line
+    pub fn resize(&mut self) -> io::Result<()> {
+        let (columns, rows) = terminal::size()?;
+        let columns = (columns as usize).max(1);
+        let rows = (rows as usize).saturating_sub(1).max(1);
+
+        self.scale = WIDTH
+            .div_ceil(columns)
+            .max(HEIGHT.div_ceil(rows * 2))
+            .max(1);
+        self.last.clear();
+        execute!(self.out, Clear(ClearType::All))
+    }
+
+    pub fn draw(&mut self, display: &FramebufferDisplay) -> io::Result<()> {
+        if self.last == display.raw() {
+            return Ok(());
+        }
+        self.last = display.raw().to_vec();
+
+        let rgb = display.to_rgb888();
+        let columns = WIDTH / self.scale;
+        let rows = HEIGHT / (self.scale * 2);
+
+        let mut line = String::new();
+        for row in 0..rows {
+            line.clear();
+            for column in 0..columns {
+                let (r, g, b) = self.average(&rgb, column, row * 2);
+                let _ = write!(line, "\x1b[38;2;{};{};{}m", r, g, b);
+                let (r, g, b) = self.average(&rgb, column, row * 2 + 1);
+                let _ = write!(line, "\x1b[48;2;{};{};{}m\u{2580}", r, g, b);
+            }
+            line.push_str("\x1b[0m");
+            queue!(self.out, MoveTo(0, row as u16), Print(&line))?;
+        }
+        queue!(self.out, MoveTo(0, rows as u16), Print(HELP))?;
+        self.out.flush()
+    }
+
+    /// Mean colour of the `scale`x`scale` block of pixels behind a half cell
+    fn average(&self, rgb: &[u8], column: usize, half_row: usize) -> (u32, u32, u32) {
+        let (mut r, mut g, mut b) = (0, 0, 0);
+        for y in half_row * self.scale..(half_row + 1) * self.scale {
+            for x in column * self.scale..(column + 1) * self.scale {
+                let i = (y * WIDTH + x) * 3;
+                r += rgb[i] as u32;
+                g += rgb[i + 1] as u32;
+                b += rgb[i + 2] as u32;
+            }
+        }
+        let n = (self.scale * self.scale) as u32;
+        (r / n, g / n, b / n)
+    }
+}
+
+impl Drop for Terminal {
+    fn drop(&mut self) {
+        let _ = execute!(self.out, Show, LeaveAlternateScreen);
+        let _ = disable_raw_mode();
+    }
+}
@@ -6,12 +6,31 @@ import numpy.typing as tp
 from PIL import Image
 
 
+DEPTHS = {"_2bpp": 2, "_4bpp": 4}
+
+
+def _depth(path: Path) -> int:
+    """Bits per pixel of a sheet, from a `_2bpp` or `_4bpp` suffix, 1 otherwise"""
+    for suffix, depth in DEPTHS.items():
+        if path.stem.endswith(suffix):
+            return depth
+    return 1
+
+
 def _compress(path: Path) -> tp.ArrayLike:
     image = Image.open(path)
-    data = image.getdata()
-    data = [any(pixel) for pixel in data]
+    depth = _depth(path)
+    if depth == 1:
+        data = [any(pixel) for pixel in image.getdata()]
+        return np.packbits(data, bitorder="little")
 
-    return np.packbits(data, bitorder="little")
+    # Brightness picks the palette index, leftmost pixel in the lowest bits
+    levels = (1 << depth) - 1
+    data = np.array(image.convert("L").getdata(), dtype=np.uint16)
+    values = ((data * levels + 127) // 255).astype(np.uint8)
+    shifts = np.arange(0, 8, depth, dtype=np.uint8)
+    packed = values.reshape(-1, len(shifts)) << shifts
+    return np.bitwise_or.reduce(packed, axis=1).astype(np.uint8)
 
 
 def compress(src: Path, dest: Path) -> None:
@@ -22,6 +41,9 @@ def compress(src: Path, dest: Path) -> None:
 
 def comress_all(src: Path, dest: Path):
     for file in src.iterdir():
-        dest_file = dest / Path(f"{file.stem}.bin")
+        stem = file.stem
+        for suffix in DEPTHS:
+            stem = stem.removesuffix(suffix)
+        dest_file = dest / Path(f"{stem}.bin")
         compress(file, dest_file)
         logging.info(f"{file} converted to {dest_file}")